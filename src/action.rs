@@ -3,9 +3,12 @@ use crate::{
         event_pipeline::{CancellableEventPipeline, EventPipelineId, EventPipelineModel},
         events::UPSEvent,
     },
-    services::watcher::UPSWatcherHandle,
-    ups::{DeviceExecutorHandle, QueryDeviceBattery, ScheduleUPSShutdown, device::Device},
-    utils::validate::is_non_zero_duration,
+    services::watcher::{DeviceEvent, UPSWatcherHandle},
+    ups::{
+        DeviceExecutorHandle, DeviceId, QueryDeviceBattery, ScheduleUPSShutdown,
+        SharedDeviceRegistry, UPSShutdownRestore, device::Device,
+    },
+    utils::validate::{is_non_zero_duration, is_valid_ups_shutdown_delay},
 };
 use anyhow::{Context, anyhow};
 use axum::http::{HeaderMap, HeaderName, HeaderValue};
@@ -39,8 +42,8 @@ type SharedActiveTasks = Arc<RwLock<Vec<EventPipelineTask>>>;
 
 /// Executor for event pipelines
 pub struct EventPipelineRunner {
-    /// Executor handle for accessing the UPS
-    executor: DeviceExecutorHandle,
+    /// Registry for accessing the UPS devices
+    registry: SharedDeviceRegistry,
     /// Database to load the event pipelines from
     db: DatabaseConnection,
     /// Watcher handle for events
@@ -55,6 +58,8 @@ pub struct EventPipelineRunner {
 struct EventPipelineTask {
     /// Unique ID for the event
     id: EventPipelineId,
+    /// ID of the device the pipeline is running for
+    device_id: DeviceId,
     /// abort handle for the task
     abort_handle: AbortHandle,
 }
@@ -64,10 +69,10 @@ impl EventPipelineRunner {
     pub fn new(
         db: DatabaseConnection,
         watcher_handle: UPSWatcherHandle,
-        executor: DeviceExecutorHandle,
+        registry: SharedDeviceRegistry,
     ) -> Self {
         Self {
-            executor,
+            registry,
             db,
             watcher_handle,
             active_tasks: Default::default(),
@@ -77,28 +82,36 @@ impl EventPipelineRunner {
 
     /// Starts a new event pipeline runner from the provided parts. Listens
     /// using the provided `watcher_handle` loading pipelines from the provided
-    /// `db` sending UPS requests to the provided `executor`
+    /// `db` sending UPS requests to the device from the `registry` that the
+    /// event occurred on
     ///
     /// This will run as a background task
     pub fn start(
         db: DatabaseConnection,
         watcher_handle: UPSWatcherHandle,
-        executor: DeviceExecutorHandle,
+        registry: SharedDeviceRegistry,
     ) {
-        let runner = Self::new(db, watcher_handle, executor);
+        let runner = Self::new(db, watcher_handle, registry);
         tokio::spawn(runner.run());
     }
 
     /// Runs the event pipelines
     pub async fn run(mut self) {
-        while let Some(event) = self.watcher_handle.next().await {
-            debug!("handling {event} event pipeline");
+        while let Some(DeviceEvent {
+            device_id, event, ..
+        }) = self.watcher_handle.next().await
+        {
+            debug!("handling {event} event pipeline for {device_id}");
 
             // Cancel pipelines that can be cancelled
-            self.cancel_pipelines(&event).await;
+            self.cancel_pipelines(&device_id, &event).await;
 
             // Find pipelines to run
-            let pipelines = match EventPipelineModel::find_by_event_enabled(&self.db, event).await {
+            let pipelines = match EventPipelineModel::find_by_event_enabled(
+                &self.db, event, &device_id,
+            )
+            .await
+            {
                 Ok(value) => value,
                 Err(err) => {
                     error!("failed to query event pipelines for event {event}: {err}");
@@ -114,12 +127,12 @@ impl EventPipelineRunner {
 
             for pipeline in pipelines {
                 // Start the event pipeline
-                self.start_pipeline(event, pipeline).await;
+                self.start_pipeline(&device_id, event, pipeline).await;
             }
         }
     }
 
-    pub async fn cancel_pipelines(&mut self, event: &UPSEvent) {
+    pub async fn cancel_pipelines(&mut self, device_id: &str, event: &UPSEvent) {
         let cancels = event.cancels();
 
         // Event cancels no other
@@ -130,7 +143,8 @@ impl EventPipelineRunner {
 
         // Find pipelines this event cancels
         let cancels_pipelines: Vec<CancellableEventPipeline> =
-            match EventPipelineModel::find_cancellable(&self.db, cancels.to_vec()).await {
+            match EventPipelineModel::find_cancellable(&self.db, cancels.to_vec(), device_id).await
+            {
                 Ok(value) => value,
                 Err(err) => {
                     error!("failed to query cancellable event pipelines for {event}: {err}");
@@ -155,9 +169,10 @@ impl EventPipelineRunner {
             .await
             // Find matching pipeline arc pointers to tasks
             .retain(|task| {
-                let is_cancel = cancels_pipelines
-                    .iter()
-                    .any(|cancel_pipeline| cancel_pipeline.id == task.id);
+                let is_cancel = task.device_id == device_id
+                    && cancels_pipelines
+                        .iter()
+                        .any(|cancel_pipeline| cancel_pipeline.id == task.id);
 
                 if is_cancel {
                     debug!("aborting running task: {}", task.id);
@@ -169,37 +184,50 @@ impl EventPipelineRunner {
     }
 
     /// Checks if theres currently an active task for the provided pipeline
-    pub async fn is_running_task(&self, id: EventPipelineId) -> bool {
+    /// on the provided device
+    pub async fn is_running_task(&self, id: EventPipelineId, device_id: &str) -> bool {
         self.active_tasks
             .read()
             .await
             .iter()
-            .any(|task| id == task.id)
+            .any(|task| id == task.id && task.device_id == device_id)
     }
 
-    pub async fn start_pipeline(&mut self, event: UPSEvent, pipeline: EventPipelineModel) {
+    pub async fn start_pipeline(
+        &mut self,
+        device_id: &str,
+        event: UPSEvent,
+        pipeline: EventPipelineModel,
+    ) {
         let id = pipeline.id;
 
-        if self.is_running_task(id).await {
+        if self.is_running_task(id, device_id).await {
             // Task is already running
             debug!("skipping event with already running task");
             return;
         }
 
+        let Some(device) = self.registry.get(device_id) else {
+            warn!("skipping event for unknown device: {device_id}");
+            return;
+        };
+
         // Spawn the task runner
         let abort_handle = self.join_set.spawn(run_pipeline(
             self.db.clone(),
             pipeline,
-            self.executor.clone(),
+            device.id.clone(),
+            device.executor.clone(),
             self.active_tasks.clone(),
             event,
         ));
 
         // Add to the active tasks
-        self.active_tasks
-            .write()
-            .await
-            .push(EventPipelineTask { id, abort_handle });
+        self.active_tasks.write().await.push(EventPipelineTask {
+            id,
+            device_id: device.id.clone(),
+            abort_handle,
+        });
     }
 }
 
@@ -210,6 +238,7 @@ impl EventPipelineRunner {
 async fn run_pipeline(
     db: DatabaseConnection,
     pipeline: EventPipelineModel,
+    device_id: DeviceId,
     executor: DeviceExecutorHandle,
     active_tasks: SharedActiveTasks,
     event: UPSEvent,
@@ -254,7 +283,7 @@ async fn run_pipeline(
     active_tasks
         .write()
         .await
-        .retain(|task| pipeline.id != task.id);
+        .retain(|task| pipeline.id != task.id || task.device_id != device_id);
 }
 
 /// Runs an event pipeline ignoring any delays and without
@@ -369,18 +398,14 @@ pub async fn await_capacity(executor: &DeviceExecutorHandle, capacity: u8) {
 
     // Loop until capacity reaches threshold
     loop {
-        let battery_state = match executor.send(QueryDeviceBattery).await {
-            Ok(value) => value,
-            Err(err) => {
-                error!("Error while requesting UPS device battery: {err:?}");
-                continue;
-            }
-        };
-
-        if battery_state.capacity < capacity {
-            return;
+        match executor.send(QueryDeviceBattery).await {
+            Ok(battery_state) if battery_state.capacity < capacity => return,
+            Ok(_) => {}
+            Err(err) => error!("Error while requesting UPS device battery: {err:?}"),
         }
 
+        // Wait for the next poll, failed requests also wait to avoid
+        // flooding the device executor with requests
         interval.tick().await;
     }
 }
@@ -593,9 +618,16 @@ pub struct ShutdownAction {
 
 #[derive(Debug, Validate, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct UPSShutdownAction {
-    /// Delay in minutes before shutting down the UPS
-    #[garde(skip)]
+    /// Delay in minutes before shutting down the UPS, pipelines stored before
+    /// the delay was validated may exceed the device range so the delay is also
+    /// limited when the action is executed
+    #[garde(custom(is_valid_ups_shutdown_delay))]
     delay_minutes: OrderedFloat<f32>,
+    /// What the UPS should do after shutting down, defaults to
+    /// turning back on after 1 minute
+    #[garde(dive)]
+    #[serde(default)]
+    restore: UPSShutdownRestore,
 }
 
 #[derive(Debug, Validate, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -761,6 +793,9 @@ pub async fn execute_notification(event: UPSEvent) -> anyhow::Result<()> {
     let icon = match event {
         UPSEvent::ACFailure => "dialog-negative",
         UPSEvent::UPSFault => "dialog-negative",
+        UPSEvent::DeviceDisconnected => "dialog-negative",
+        UPSEvent::OverloadStart => "dialog-warning",
+        UPSEvent::PowerUnstable => "dialog-warning",
         _ => "dialog-positive",
     };
 
@@ -781,6 +816,9 @@ pub async fn execute_popup(event: UPSEvent) -> anyhow::Result<()> {
     let ty = match event {
         UPSEvent::ACFailure => MessageType::Error,
         UPSEvent::UPSFault => MessageType::Error,
+        UPSEvent::DeviceDisconnected => MessageType::Error,
+        UPSEvent::OverloadStart => MessageType::Warning,
+        UPSEvent::PowerUnstable => MessageType::Warning,
         _ => MessageType::Info,
     };
 
@@ -868,11 +906,22 @@ pub async fn execute_shutdown_ups<D: Device>(
     config: &UPSShutdownAction,
     executor: &DeviceExecutorHandle<D>,
 ) -> anyhow::Result<()> {
+    let command = ScheduleUPSShutdown {
+        delay_minutes: config.delay_minutes.0,
+        restore: config.restore,
+    };
+
+    let range = ScheduleUPSShutdown::MIN_DELAY_MINUTES..=ScheduleUPSShutdown::MAX_DELAY_MINUTES;
+    if !range.contains(&command.delay_minutes) {
+        warn!(
+            "ups shutdown delay of {} minutes is outside the supported range, using {} minutes",
+            command.delay_minutes,
+            command.effective_delay_minutes()
+        );
+    }
+
     executor
-        .send(ScheduleUPSShutdown {
-            delay_minutes: config.delay_minutes.0,
-            reboot_delay_minutes: 1,
-        })
+        .send(command)
         .await
         .context("failed to schedule ups shutdown")?;
 
@@ -970,31 +1019,55 @@ pub async fn execute_http_request(
 mod test {
     use super::{
         Action, ActionDelay, ActionPipeline, ActionType, EventPipelineModel, EventPipelineRunner,
+        UPSShutdownAction,
     };
     use crate::{
         action::ExecutableAction,
+        config::HidConfig,
         database::{connect_database, entities::events::UPSEvent},
         logging::setup_test_logging,
-        services::watcher::UPSWatcherHandle,
-        ups::{DeviceExecutor, HidDeviceCreator},
+        services::watcher::{DeviceEvent, EventBus},
+        ups::{
+            AnyDeviceCreator, DeviceExecutor, DeviceRegistry, HidDeviceCreator, RegisteredDevice,
+            registry::DEFAULT_DEVICE_ID,
+        },
     };
     use chrono::Utc;
+    use garde::Validate;
     use log::debug;
-    use std::time::Duration;
-    use tokio::{sync::broadcast, time::sleep};
+    use std::{sync::Arc, time::Duration};
+    use tokio::time::sleep;
 
     fn setup_tests() {
         setup_test_logging();
     }
 
+    /// New UPS shutdown actions should be limited to the device delay range
+    /// while stored actions outside the range should still load
+    #[test]
+    fn test_ups_shutdown_action_delay() {
+        let valid: UPSShutdownAction = serde_json::from_str(r#"{ "delay_minutes": 5 }"#).unwrap();
+        assert!(valid.validate().is_ok());
+
+        let stored: UPSShutdownAction = serde_json::from_str(r#"{ "delay_minutes": 30 }"#).unwrap();
+        assert!(stored.validate().is_err());
+    }
+
     async fn test_pipeline(
         event: UPSEvent,
         pipeline: ActionPipeline,
         cancellable: bool,
     ) -> anyhow::Result<()> {
-        let (tx, rx) = broadcast::channel(8);
-        let watcher_handle = UPSWatcherHandle { rx };
-        let executor = DeviceExecutor::start(HidDeviceCreator::new()?)?;
+        let bus = EventBus::new(8);
+        let watcher_handle = bus.subscribe();
+        let executor = DeviceExecutor::start(AnyDeviceCreator::Hid(HidDeviceCreator::new(
+            &HidConfig::default(),
+        )?));
+        let registry = Arc::new(DeviceRegistry::new(vec![RegisteredDevice {
+            id: DEFAULT_DEVICE_ID.to_string(),
+            executor,
+            simulation: None,
+        }])?);
 
         // Use in memory database for event pipelines
         let db = connect_database("sqlite::memory:").await;
@@ -1003,6 +1076,7 @@ mod test {
             &db,
             "Test action".to_string(),
             event,
+            None,
             pipeline,
             cancellable,
             Utc::now(),
@@ -1010,11 +1084,16 @@ mod test {
         .await?;
         debug!("spawning runner");
 
-        tokio::spawn(EventPipelineRunner::new(db, watcher_handle, executor).run());
+        tokio::spawn(EventPipelineRunner::new(db, watcher_handle, registry).run());
 
         debug!("sending event");
 
-        tx.send(UPSEvent::ACFailure)?;
+        bus.send(DeviceEvent {
+            device_id: DEFAULT_DEVICE_ID.to_string(),
+            event: UPSEvent::ACFailure,
+            battery_test: None,
+            snapshot: None,
+        });
 
         // Sleep for 1 minute to allow test a chance to run
         sleep(Duration::from_secs(60)).await;
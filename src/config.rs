@@ -1,14 +1,21 @@
 use std::{
+    collections::HashMap,
     net::{IpAddr, Ipv4Addr},
-    path::Path,
+    path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::Context;
-use log::{error, LevelFilter};
-use serde::Deserialize;
+use log::{error, warn, LevelFilter};
+use serde::{Deserialize, Deserializer};
 use std::fs::read_to_string;
 
+use crate::{
+    nut::DEFAULT_NUT_PORT,
+    ups::{DeviceState, models::DevicePowerState, protocol::Protocol, registry::DEFAULT_DEVICE_ID},
+};
+
 /// Linux release builds load config from /etc/oguard
 #[cfg(all(target_os = "linux", not(debug_assertions)))]
 const CONFIG_PATH: &str = "/etc/oguard/config.toml";
@@ -34,6 +41,64 @@ pub struct Config {
     pub login: LoginConfig,
     /// Logging configuration
     pub logging: LoggingConfig,
+    /// Primary UPS device configuration
+    pub device: DeviceConfig,
+    /// Additional UPS devices to monitor, each must have an explicit `id`
+    #[serde(deserialize_with = "deserialize_devices")]
+    pub devices: Vec<DeviceConfig>,
+    /// NUT (Network UPS Tools) server configuration
+    pub nut: NutConfig,
+    /// Raw command console configuration
+    pub raw_commands: RawCommandsConfig,
+    /// Device state watcher configuration
+    pub watcher: WatcherConfig,
+    /// History tracking configuration
+    pub history: HistoryConfig,
+}
+
+impl Config {
+    /// Iterates the configuration for every device starting with the primary device
+    pub fn device_configs(&self) -> impl Iterator<Item = &DeviceConfig> {
+        std::iter::once(&self.device).chain(self.devices.iter())
+    }
+
+    /// Replaces the primary device with a device replaying the capture
+    /// file at the provided `path`, additional devices are removed
+    pub fn replay_device(&mut self, path: PathBuf) {
+        self.device.kind = DeviceKind::Replay;
+        self.device.replay.path = path;
+        self.device.record = None;
+        self.devices.clear();
+    }
+
+    /// Checks that every device has a unique ID, done when the config
+    /// is loaded so no devices are started for an invalid config
+    fn check_device_ids(&self) -> anyhow::Result<()> {
+        let devices: Vec<&DeviceConfig> = self.device_configs().collect();
+
+        for (index, device) in devices.iter().enumerate() {
+            if devices[..index].iter().any(|other| other.id == device.id) {
+                return Err(anyhow::anyhow!("duplicate device id: {}", device.id));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Corrects values that would break the services using them, logs
+    /// a warning for each corrected value. Called once logging is set up
+    pub fn validate(&mut self) {
+        self.watcher.power.flap.validate();
+        self.watcher.polling.validate();
+        self.history.validate();
+    }
+
+    /// Replaces every configured device with a simulated device
+    pub fn simulate_devices(&mut self) {
+        std::iter::once(&mut self.device)
+            .chain(self.devices.iter_mut())
+            .for_each(|device| device.kind = DeviceKind::Simulated);
+    }
 }
 
 impl Default for Config {
@@ -43,6 +108,12 @@ impl Default for Config {
             http: Default::default(),
             login: Default::default(),
             logging: Default::default(),
+            device: Default::default(),
+            devices: Default::default(),
+            nut: Default::default(),
+            raw_commands: Default::default(),
+            watcher: Default::default(),
+            history: Default::default(),
         }
     }
 }
@@ -82,6 +153,280 @@ impl Default for HttpConfig {
     }
 }
 
+/// Configurations for the NUT (Network UPS Tools) server, allows NUT clients
+/// such as upsmon to monitor the devices
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct NutConfig {
+    /// Whether to run the NUT server
+    pub enabled: bool,
+    /// Host to bind the server on
+    pub host: IpAddr,
+    /// Port to bind the server on
+    pub port: u16,
+    /// Username NUT clients log in with
+    pub username: String,
+    /// Password NUT clients log in with, if not set clients
+    /// will not be able to log in or set forced shutdown
+    pub password: Option<String>,
+}
+
+impl Default for NutConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: DEFAULT_NUT_PORT,
+            username: "upsmon".to_string(),
+            password: None,
+        }
+    }
+}
+
+/// Configuration for the raw command console, commands can only be sent when
+/// they start with one of the allowed prefixes. No commands are allowed by
+/// default as some commands have physical effects (i.e shutdown)
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct RawCommandsConfig {
+    /// Prefixes of the commands that are allowed to be sent
+    pub allowed_prefixes: Vec<String>,
+}
+
+impl RawCommandsConfig {
+    /// Checks if the provided command is allowed to be sent
+    pub fn is_allowed(&self, command: &str) -> bool {
+        self.allowed_prefixes
+            .iter()
+            .any(|prefix| command.starts_with(prefix.as_str()))
+    }
+}
+
+/// Configuration for the watchers that turn device state changes into events
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct WatcherConfig {
+    /// Thresholds for the overload events
+    pub overload: OverloadConfig,
+    /// Debouncing and flap detection for AC power transitions
+    pub power: PowerConfig,
+    /// Intervals to poll the device state at
+    pub polling: PollingConfig,
+}
+
+/// Intervals the watcher polls the device state at, the device is polled
+/// faster while on battery or self testing so changes are caught quickly
+/// and slower once the device has been on AC power for a long time
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct PollingConfig {
+    /// Milliseconds between polls while on AC power
+    pub interval_ms: u64,
+    /// Milliseconds between polls while on battery or self testing
+    pub active_interval_ms: u64,
+    /// Milliseconds between polls once on AC power for `idle_after_secs`
+    pub idle_interval_ms: u64,
+    /// Seconds on AC power before polling at the idle interval
+    pub idle_after_secs: u64,
+}
+
+impl PollingConfig {
+    /// Smallest allowed poll interval, anything shorter floods the device
+    const MIN_INTERVAL_MS: u64 = 100;
+
+    /// Corrects intervals that would poll the device in a busy loop
+    fn validate(&mut self) {
+        for (name, interval_ms) in [
+            ("interval_ms", &mut self.interval_ms),
+            ("active_interval_ms", &mut self.active_interval_ms),
+            ("idle_interval_ms", &mut self.idle_interval_ms),
+        ] {
+            if *interval_ms < Self::MIN_INTERVAL_MS {
+                warn!(
+                    "watcher.polling.{name} must be at least {}, using {}",
+                    Self::MIN_INTERVAL_MS,
+                    Self::MIN_INTERVAL_MS
+                );
+                *interval_ms = Self::MIN_INTERVAL_MS;
+            }
+        }
+    }
+
+    /// Determines the interval before the next poll for the provided
+    /// device state and the time the device has been on AC power for
+    pub fn interval(&self, state: Option<&DeviceState>, on_line_for: Option<Duration>) -> Duration {
+        let interval_ms = match state {
+            Some(state)
+                if state.device_power_state == DevicePowerState::Battery
+                    || state.battery_self_test =>
+            {
+                self.active_interval_ms
+            }
+            _ if on_line_for
+                .is_some_and(|on_line_for| on_line_for.as_secs() >= self.idle_after_secs) =>
+            {
+                self.idle_interval_ms
+            }
+            _ => self.interval_ms,
+        };
+
+        Duration::from_millis(interval_ms)
+    }
+}
+
+impl Default for PollingConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 3000,
+            active_interval_ms: 1000,
+            idle_interval_ms: 10000,
+            idle_after_secs: 600,
+        }
+    }
+}
+
+/// Configuration for the history tracker that stores
+/// the device state and battery to the database
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    /// Seconds between each stored device state and battery
+    pub interval_secs: u64,
+}
+
+impl HistoryConfig {
+    /// Corrects an interval the history tracker cannot run at
+    fn validate(&mut self) {
+        if self.interval_secs == 0 {
+            let interval_secs = Self::default().interval_secs;
+            warn!("history.interval_secs cannot be zero, using {interval_secs}");
+            self.interval_secs = interval_secs;
+        }
+    }
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self { interval_secs: 60 }
+    }
+}
+
+/// Configuration for debouncing AC power transitions, prevents brown-outs
+/// from repeatedly starting and cancelling pipelines
+#[derive(Debug, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct PowerConfig {
+    /// How long the device must stay on battery before an AC failure is emitted
+    pub ac_failure: DebounceConfig,
+    /// How long the device must stay on AC power before an AC recovery is emitted
+    pub ac_recovery: DebounceConfig,
+    /// Detection of a line that keeps bouncing between AC and battery power
+    pub flap: FlapConfig,
+}
+
+/// How long a new state must hold before it is accepted, the state must
+/// be seen for at least `polls` consecutive polls and `seconds` seconds
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct DebounceConfig {
+    /// Number of consecutive polls the state must be seen for
+    pub polls: u32,
+    /// Number of seconds the state must hold for
+    pub seconds: u64,
+}
+
+impl Default for DebounceConfig {
+    fn default() -> Self {
+        Self {
+            polls: 1,
+            seconds: 0,
+        }
+    }
+}
+
+/// Flap detection for AC power, the line is unstable when the power state
+/// changes `max_transitions` times within `window_secs` seconds. AC power
+/// events are held off until the line has not changed for `window_secs`
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct FlapConfig {
+    /// Whether to detect an unstable line
+    pub enabled: bool,
+    /// Number of power state changes within the window that make the line unstable
+    pub max_transitions: usize,
+    /// Seconds to count power state changes over
+    pub window_secs: u64,
+}
+
+impl FlapConfig {
+    /// Smallest number of transitions that can make the line unstable, fewer
+    /// would report the line as unstable on every poll
+    const MIN_TRANSITIONS: usize = 2;
+
+    /// Corrects values that would hold off power events forever
+    fn validate(&mut self) {
+        if self.max_transitions < Self::MIN_TRANSITIONS {
+            warn!(
+                "watcher.power.flap.max_transitions must be at least {}, using {}",
+                Self::MIN_TRANSITIONS,
+                Self::MIN_TRANSITIONS
+            );
+            self.max_transitions = Self::MIN_TRANSITIONS;
+        }
+
+        if self.window_secs == 0 {
+            let window_secs = Self::default().window_secs;
+            warn!("watcher.power.flap.window_secs cannot be zero, using {window_secs}");
+            self.window_secs = window_secs;
+        }
+    }
+}
+
+impl Default for FlapConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_transitions: 4,
+            window_secs: 60,
+        }
+    }
+}
+
+/// Thresholds for the output load overload events, the load must drop
+/// below the end threshold before the overload is considered over so
+/// a load hovering around the start threshold doesn't flap
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct OverloadConfig {
+    /// Output load percent at or above which the device is overloaded
+    pub start_percent: u8,
+    /// Output load percent at or below which the overload has ended
+    pub end_percent: u8,
+}
+
+impl OverloadConfig {
+    /// Determines whether the device is overloaded at the provided load
+    /// based on whether it was previously `overloaded`
+    pub fn is_overloaded(&self, overloaded: bool, load_percent: u8) -> bool {
+        if overloaded {
+            // End threshold must be below the start threshold to prevent flapping
+            let end_percent = self.end_percent.min(self.start_percent.saturating_sub(1));
+            load_percent > end_percent
+        } else {
+            load_percent >= self.start_percent
+        }
+    }
+}
+
+impl Default for OverloadConfig {
+    fn default() -> Self {
+        Self {
+            start_percent: 90,
+            end_percent: 80,
+        }
+    }
+}
+
 /// Login configuration, by default there is no credentials
 /// and the server cannot be logged in until the user sets
 /// credentials in the file
@@ -92,6 +437,142 @@ pub struct LoginConfig {
     pub password: Option<String>,
 }
 
+/// Configuration for a UPS device
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DeviceConfig {
+    /// Unique ID for the device, used to identify the device in
+    /// the API and stored history
+    pub id: String,
+    /// Transport used to communicate with the device
+    pub kind: DeviceKind,
+    /// HID device selection, used when `kind` is [DeviceKind::Hid]
+    pub hid: HidConfig,
+    /// Serial port configuration, used when `kind` is [DeviceKind::Serial]
+    pub serial: SerialConfig,
+    /// Simulation configuration, used when `kind` is [DeviceKind::Simulated]
+    pub simulated: SimulatedConfig,
+    /// Remote NUT server configuration, used when `kind` is [DeviceKind::Nut]
+    pub nut: NutDeviceConfig,
+    /// Replay configuration, used when `kind` is [DeviceKind::Replay]
+    pub replay: ReplayConfig,
+    /// Path to a capture file to append the raw device traffic to
+    pub record: Option<PathBuf>,
+    /// Protocol the device speaks, detected by probing the device when not set
+    pub protocol: Option<Protocol>,
+    /// Milliseconds to cache the responses for each command, keyed by the
+    /// protocol command (i.e "QS" for the device state). Durations for the
+    /// `QS` protocol commands apply to the commands of every protocol
+    pub cache_ttl_ms: HashMap<String, u64>,
+}
+
+impl Default for DeviceConfig {
+    fn default() -> Self {
+        Self {
+            id: DEFAULT_DEVICE_ID.to_string(),
+            kind: Default::default(),
+            hid: Default::default(),
+            serial: Default::default(),
+            simulated: Default::default(),
+            nut: Default::default(),
+            replay: Default::default(),
+            record: None,
+            protocol: None,
+            cache_ttl_ms: Default::default(),
+        }
+    }
+}
+
+/// Transport used to communicate with the device
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceKind {
+    /// USB HID device
+    #[default]
+    Hid,
+    /// RS-232 or USB-serial adapter
+    Serial,
+    /// In memory simulated device for development and demos
+    Simulated,
+    /// UPS provided by a remote NUT (Network UPS Tools) server
+    Nut,
+    /// Device that plays back a capture file
+    Replay,
+}
+
+/// Configurations for selecting a HID device, when multiple devices match
+/// the first matching device will be used
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HidConfig {
+    /// Vendor ID of the device
+    pub vendor_id: u16,
+    /// Product ID of the device
+    pub product_id: u16,
+    /// Platform specific path of the device, takes priority over the
+    /// vendor and product IDs when set
+    pub path: Option<String>,
+    /// Serial number of the device
+    pub serial_number: Option<String>,
+}
+
+impl Default for HidConfig {
+    fn default() -> Self {
+        // Dynamix UPSD2000 Defender
+        Self {
+            vendor_id: 0x0665,
+            product_id: 0x5161,
+            path: None,
+            serial_number: None,
+        }
+    }
+}
+
+/// Configurations for a serial port device
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SerialConfig {
+    /// Path to the serial port (i.e /dev/ttyUSB0 or COM1)
+    pub port: String,
+    /// Baud rate for the port
+    pub baud_rate: u32,
+    /// Parity checking mode
+    pub parity: SerialParity,
+    /// Number of bits per character (5-8)
+    pub data_bits: u8,
+    /// Number of stop bits (1-2)
+    pub stop_bits: u8,
+    /// Time in milliseconds to wait for a response
+    pub timeout_ms: u64,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        // Megatec protocol devices use 2400 8N1
+        Self {
+            #[cfg(windows)]
+            port: "COM1".to_string(),
+            #[cfg(not(windows))]
+            port: "/dev/ttyUSB0".to_string(),
+            baud_rate: 2400,
+            parity: SerialParity::None,
+            data_bits: 8,
+            stop_bits: 1,
+            timeout_ms: 3000,
+        }
+    }
+}
+
+/// Parity checking mode for a serial port
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SerialParity {
+    #[default]
+    None,
+    Odd,
+    Even,
+}
+
 /// Loads the user configuration from their expected configuration
 /// path see [CONFIG_PATH]
 pub fn load_user() -> Config {
@@ -113,5 +594,209 @@ pub fn from_file(path: &Path) -> anyhow::Result<Config> {
 
 /// Loads a configuration from a string
 pub fn from_str(value: &str) -> anyhow::Result<Config> {
-    toml::from_str(value).context("failed to parse config")
+    let config: Config = toml::from_str(value).context("failed to parse config")?;
+    config.check_device_ids()?;
+    Ok(config)
+}
+
+/// Deserializes the additional devices, unlike the primary device these
+/// don't have a default ID as it would collide with the primary device
+fn deserialize_devices<'de, D>(deserializer: D) -> Result<Vec<DeviceConfig>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    struct NamedDeviceConfig {
+        id: String,
+        #[serde(flatten)]
+        config: DeviceConfig,
+    }
+
+    let devices = Vec::<NamedDeviceConfig>::deserialize(deserializer)?;
+    Ok(devices
+        .into_iter()
+        .map(|NamedDeviceConfig { id, config }| DeviceConfig { id, ..config })
+        .collect())
+}
+
+/// Configurations for a simulated device
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SimulatedConfig {
+    /// Initial percentage load on the UPS output
+    pub load_percent: u8,
+    /// Minutes a full battery lasts at 100% load
+    pub full_load_runtime_minutes: f64,
+    /// Multiplier for how fast the battery drains and charges, useful
+    /// for running through a full discharge quickly
+    pub time_scale: f64,
+}
+
+impl Default for SimulatedConfig {
+    fn default() -> Self {
+        Self {
+            load_percent: 20,
+            full_load_runtime_minutes: 8.0,
+            time_scale: 1.0,
+        }
+    }
+}
+
+/// Configurations for a device replaying a capture file
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct ReplayConfig {
+    /// Path to the capture file to replay
+    pub path: PathBuf,
+    /// Whether to start again from the beginning of the capture
+    /// once the end is reached
+    pub repeat: bool,
+}
+
+/// Configurations for a device using a remote NUT (Network UPS Tools) server
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct NutDeviceConfig {
+    /// Host of the NUT server
+    pub host: String,
+    /// Port of the NUT server
+    pub port: u16,
+    /// Name of the UPS on the NUT server
+    pub ups: String,
+    /// Optional username to log in to the UPS with
+    pub username: Option<String>,
+    /// Optional password to log in to the UPS with
+    pub password: Option<String>,
+    /// Time in milliseconds to wait for a response
+    pub timeout_ms: u64,
+}
+
+impl Default for NutDeviceConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: DEFAULT_NUT_PORT,
+            ups: "ups".to_string(),
+            username: None,
+            password: None,
+            timeout_ms: 3000,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{FlapConfig, HistoryConfig, OverloadConfig, PollingConfig};
+    use crate::ups::{models::DevicePowerState, protocol::Protocol};
+
+    /// Overload should only end once the load drops below the end threshold
+    #[test]
+    fn test_overload_hysteresis() {
+        let config = OverloadConfig {
+            start_percent: 90,
+            end_percent: 80,
+        };
+
+        assert!(!config.is_overloaded(false, 89));
+        assert!(config.is_overloaded(false, 90));
+        assert!(config.is_overloaded(true, 85));
+        assert!(config.is_overloaded(true, 81));
+        assert!(!config.is_overloaded(true, 80));
+    }
+
+    /// Polling should speed up on battery and slow down after a long time on AC power
+    #[test]
+    fn test_polling_interval() {
+        let config = PollingConfig::default();
+        let mut state = Protocol::Qs
+            .parse_state("(208.4 140.0 208.4 034 59.9 2.05 35.0 00000000".into())
+            .unwrap();
+
+        assert_eq!(config.interval(None, None), Duration::from_secs(3));
+        assert_eq!(
+            config.interval(Some(&state), Some(Duration::from_secs(60))),
+            Duration::from_secs(3)
+        );
+        assert_eq!(
+            config.interval(Some(&state), Some(Duration::from_secs(600))),
+            Duration::from_secs(10)
+        );
+
+        state.device_power_state = DevicePowerState::Battery;
+        assert_eq!(config.interval(Some(&state), None), Duration::from_secs(1));
+    }
+
+    /// Flap detection values that would hold off power events forever should be corrected
+    #[test]
+    fn test_validate_flap() {
+        let mut config = FlapConfig {
+            enabled: true,
+            max_transitions: 0,
+            window_secs: 0,
+        };
+        config.validate();
+
+        assert_eq!(config.max_transitions, 2);
+        assert_eq!(config.window_secs, 60);
+    }
+
+    /// Intervals that would busy loop or panic should be corrected
+    #[test]
+    fn test_validate_intervals() {
+        let mut polling = PollingConfig {
+            interval_ms: 0,
+            active_interval_ms: 50,
+            idle_interval_ms: 0,
+            idle_after_secs: 600,
+        };
+        polling.validate();
+
+        assert_eq!(polling.interval_ms, 100);
+        assert_eq!(polling.active_interval_ms, 100);
+        assert_eq!(polling.idle_interval_ms, 100);
+
+        let mut history = HistoryConfig { interval_secs: 0 };
+        history.validate();
+
+        assert_eq!(history.interval_secs, 60);
+    }
+
+    /// Additional devices should require a unique ID
+    #[test]
+    fn test_device_ids() {
+        let config = super::from_str(
+            r#"
+            [[devices]]
+            id = "office"
+            kind = "serial"
+
+            [devices.serial]
+            port = "/dev/ttyUSB1"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.devices[0].id, "office");
+        assert_eq!(config.devices[0].serial.port, "/dev/ttyUSB1");
+
+        let missing = super::from_str(
+            r#"
+            [[devices]]
+            kind = "serial"
+            "#,
+        );
+        assert!(missing.is_err());
+
+        let duplicate = super::from_str(
+            r#"
+            [device]
+            id = "office"
+
+            [[devices]]
+            id = "office"
+            "#,
+        );
+        assert!(duplicate.is_err());
+    }
 }
@@ -1,5 +1,5 @@
 use crate::database::DbResult;
-use crate::ups::DeviceBattery;
+use crate::ups::{DeviceBattery, DeviceId};
 use futures::future::BoxFuture;
 use sea_orm::entity::prelude::*;
 use sea_orm::{
@@ -21,6 +21,9 @@ pub struct Model {
     #[serde(skip)]
     pub id: i64,
 
+    /// ID of the device the state is for
+    pub device_id: DeviceId,
+
     /// The state event
     pub state: DeviceBattery,
 
@@ -36,25 +39,33 @@ impl ActiveModelBehavior for ActiveModel {}
 impl Model {
     pub fn create(
         db: &DatabaseConnection,
+        device_id: DeviceId,
         state: DeviceBattery,
         created_at: DateTimeUtc,
     ) -> BoxFuture<'_, DbResult<Self>> {
         ActiveModel {
             id: NotSet,
+            device_id: Set(device_id),
             state: Set(state),
             created_at: Set(created_at),
         }
         .insert(db)
     }
 
+    /// Gets the entries created within the provided range, optionally
+    /// only including entries for a specific device
     pub async fn get_range(
         db: &DatabaseConnection,
+        device_id: Option<&str>,
         start: DateTimeUtc,
         end: DateTimeUtc,
     ) -> DbResult<Vec<Self>> {
-        Entity::find()
-            .filter(Column::CreatedAt.between(start, end))
-            .all(db)
-            .await
+        let mut query = Entity::find().filter(Column::CreatedAt.between(start, end));
+
+        if let Some(device_id) = device_id {
+            query = query.filter(Column::DeviceId.eq(device_id));
+        }
+
+        query.all(db).await
     }
 }
@@ -1,5 +1,6 @@
 use crate::action::ActionPipeline;
 use crate::database::DbResult;
+use crate::ups::DeviceId;
 use async_trait::async_trait;
 use chrono::Utc;
 use futures::future::BoxFuture;
@@ -10,7 +11,7 @@ use sea_orm::{
     ActiveValue::{NotSet, Set},
     DatabaseConnection,
 };
-use sea_orm::{FromQueryResult, IntoActiveModel, QuerySelect};
+use sea_orm::{Condition, FromQueryResult, IntoActiveModel, QuerySelect};
 use serde::Serialize;
 
 use super::events::UPSEvent;
@@ -33,6 +34,9 @@ pub struct Model {
     /// The event this pipeline is for
     pub event: UPSEvent,
 
+    /// Device this pipeline is for, runs for events from any device when [None]
+    pub device_id: Option<DeviceId>,
+
     /// Pipeline of actions to run
     pub pipeline: ActionPipeline,
 
@@ -94,6 +98,9 @@ pub struct ListEventPipeline {
     /// The event this pipeline is for
     pub event: UPSEvent,
 
+    /// Device this pipeline is for, runs for events from any device when [None]
+    pub device_id: Option<DeviceId>,
+
     /// Whether the events that cancel this should abort the run
     pub cancellable: bool,
 
@@ -113,6 +120,7 @@ impl Model {
         db: &DatabaseConnection,
         name: String,
         event: UPSEvent,
+        device_id: Option<DeviceId>,
         pipeline: ActionPipeline,
         cancellable: bool,
         created_at: DateTimeUtc,
@@ -121,6 +129,7 @@ impl Model {
             id: NotSet,
             name: Set(name),
             event: Set(event),
+            device_id: Set(device_id),
             pipeline: Set(pipeline),
             cancellable: Set(cancellable),
             enabled: Set(true),
@@ -151,27 +160,39 @@ impl Model {
         Entity::find().filter(Column::Event.eq(event)).all(db).await
     }
 
+    /// Condition matching pipelines for the provided device or pipelines for any device
+    fn for_device(device_id: &str) -> Condition {
+        Condition::any()
+            .add(Column::DeviceId.is_null())
+            .add(Column::DeviceId.eq(device_id))
+    }
+
     pub async fn delete(db: &DatabaseConnection, id: EventPipelineId) -> DbResult<bool> {
         let res = Entity::delete_by_id(id).exec(db).await?;
         debug!("affected {}", res.rows_affected);
         Ok(res.rows_affected != 0)
     }
 
+    /// Finds the enabled pipelines for the provided event on the provided device
     pub async fn find_by_event_enabled(
         db: &DatabaseConnection,
         event: UPSEvent,
+        device_id: &str,
     ) -> DbResult<Vec<Self>> {
         Entity::find()
             .filter(Column::Event.eq(event).and(Column::Enabled.eq(true)))
+            .filter(Self::for_device(device_id))
             .all(db)
             .await
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn update(
         self,
         db: &DatabaseConnection,
         name: Option<String>,
         event: Option<UPSEvent>,
+        device_id: Option<Option<DeviceId>>,
         pipeline: Option<ActionPipeline>,
         cancellable: Option<bool>,
         enabled: Option<bool>,
@@ -185,6 +206,10 @@ impl Model {
             active_model.event = Set(event);
         }
 
+        if let Some(device_id) = device_id {
+            active_model.device_id = Set(device_id);
+        }
+
         if let Some(pipeline) = pipeline {
             active_model.pipeline = Set(pipeline);
         }
@@ -212,10 +237,11 @@ impl Model {
         Ok(())
     }
 
-    /// Finds cancellable pipelines for the provided events
+    /// Finds cancellable pipelines for the provided events on the provided device
     pub async fn find_cancellable(
         db: &DatabaseConnection,
         events: Vec<UPSEvent>,
+        device_id: &str,
     ) -> DbResult<Vec<CancellableEventPipeline>> {
         Entity::find()
             .select_only()
@@ -229,6 +255,7 @@ impl Model {
                     .and(Column::Cancellable.eq(true))
                     .and(Column::Enabled.eq(true)),
             )
+            .filter(Self::for_device(device_id))
             .into_model::<CancellableEventPipeline>()
             .all(db)
             .await
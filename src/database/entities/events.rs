@@ -1,4 +1,5 @@
 use crate::database::DbResult;
+use crate::ups::{BatteryTestMode, DeviceId, DeviceSnapshot};
 use futures::future::BoxFuture;
 use sea_orm::entity::prelude::*;
 use sea_orm::{
@@ -21,11 +22,24 @@ pub struct Model {
     #[serde(skip)]
     pub id: i64,
 
+    /// ID of the device the event occurred on
+    pub device_id: DeviceId,
+
     /// Type of event that occurred
     #[sea_orm(column_name = "type")]
     #[serde(rename = "type")]
     pub ty: UPSEvent,
 
+    /// Kind of battery test that was run, only present for battery
+    /// test events where the test was requested through the API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub battery_test: Option<BatteryTestMode>,
+
+    /// Snapshot of the device when the event occurred, not present
+    /// for connection events or events recorded before snapshots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<DeviceSnapshot>,
+
     /// Creation time for the event
     pub created_at: DateTimeUtc,
 }
@@ -57,6 +71,28 @@ pub enum UPSEvent {
     /// UPS Battery test has ended
     #[sea_orm(num_value = 6)]
     BatteryTestEnd,
+    /// Connection to the UPS device has been lost
+    #[sea_orm(num_value = 7)]
+    DeviceDisconnected,
+    /// Connection to the UPS device has been restored
+    #[sea_orm(num_value = 8)]
+    DeviceReconnected,
+    /// UPS is no longer in a fault state
+    #[sea_orm(num_value = 9)]
+    UPSFaultCleared,
+    /// UPS output load has exceeded the overload threshold
+    #[sea_orm(num_value = 10)]
+    OverloadStart,
+    /// UPS output load has returned below the overload threshold
+    #[sea_orm(num_value = 11)]
+    OverloadEnd,
+    /// AC power is repeatedly switching between AC and battery power
+    #[sea_orm(num_value = 12)]
+    PowerUnstable,
+    /// Service has started watching the device, the snapshot holds
+    /// the conditions that were already active at startup
+    #[sea_orm(num_value = 13)]
+    ServiceStarted,
 }
 
 impl UPSEvent {
@@ -65,11 +101,18 @@ impl UPSEvent {
         match self {
             UPSEvent::ACFailure => &[UPSEvent::ACRecovery],
             UPSEvent::ACRecovery => &[UPSEvent::ACFailure],
-            UPSEvent::UPSFault => &[],
+            UPSEvent::UPSFault => &[UPSEvent::UPSFaultCleared],
+            UPSEvent::UPSFaultCleared => &[UPSEvent::UPSFault],
+            UPSEvent::OverloadStart => &[UPSEvent::OverloadEnd],
+            UPSEvent::OverloadEnd => &[UPSEvent::OverloadStart],
+            UPSEvent::PowerUnstable => &[],
+            UPSEvent::ServiceStarted => &[],
             UPSEvent::LowBatteryModeStart => &[UPSEvent::LowBatteryModeEnd],
             UPSEvent::LowBatteryModeEnd => &[UPSEvent::LowBatteryModeStart],
             UPSEvent::BatteryTestStart => &[UPSEvent::BatteryTestEnd],
             UPSEvent::BatteryTestEnd => &[UPSEvent::BatteryTestStart],
+            UPSEvent::DeviceDisconnected => &[UPSEvent::DeviceReconnected],
+            UPSEvent::DeviceReconnected => &[UPSEvent::DeviceDisconnected],
         }
     }
 }
@@ -82,25 +125,83 @@ impl ActiveModelBehavior for ActiveModel {}
 impl Model {
     pub fn create(
         db: &DatabaseConnection,
+        device_id: DeviceId,
         ty: UPSEvent,
+        battery_test: Option<BatteryTestMode>,
+        snapshot: Option<DeviceSnapshot>,
         created_at: DateTimeUtc,
     ) -> BoxFuture<'_, DbResult<Self>> {
         ActiveModel {
             id: NotSet,
+            device_id: Set(device_id),
             ty: Set(ty),
+            battery_test: Set(battery_test),
+            snapshot: Set(snapshot),
             created_at: Set(created_at),
         }
         .insert(db)
     }
 
+    /// Gets the entries created within the provided range, optionally
+    /// only including entries for a specific device
     pub async fn get_range(
         db: &DatabaseConnection,
+        device_id: Option<&str>,
         start: DateTimeUtc,
         end: DateTimeUtc,
     ) -> DbResult<Vec<Self>> {
-        Entity::find()
-            .filter(Column::CreatedAt.between(start, end))
-            .all(db)
+        let mut query = Entity::find().filter(Column::CreatedAt.between(start, end));
+
+        if let Some(device_id) = device_id {
+            query = query.filter(Column::DeviceId.eq(device_id));
+        }
+
+        query.all(db).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+
+    use super::{EventModel, UPSEvent};
+    use crate::{
+        database::connect_database,
+        ups::{DeviceBattery, DeviceSnapshot, Protocol, registry::DEFAULT_DEVICE_ID},
+    };
+
+    /// Snapshots should be stored with the event and loaded back
+    #[tokio::test]
+    async fn test_event_snapshot() {
+        let db = connect_database("sqlite::memory:").await;
+
+        let state = Protocol::Qs
+            .parse_state("(208.4 140.0 208.4 034 59.9 2.05 35.0 10000000".into())
+            .unwrap();
+        let snapshot = DeviceSnapshot {
+            state,
+            battery: Some(DeviceBattery {
+                capacity: 87,
+                remaining_time: 1200,
+            }),
+        };
+
+        let start = Utc::now();
+        EventModel::create(
+            &db,
+            DEFAULT_DEVICE_ID.to_string(),
+            UPSEvent::ACFailure,
+            None,
+            Some(snapshot.clone()),
+            Utc::now(),
+        )
+        .await
+        .unwrap();
+
+        let events = EventModel::get_range(&db, None, start, Utc::now())
             .await
+            .unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].snapshot, Some(snapshot));
     }
 }
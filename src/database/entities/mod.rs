@@ -2,3 +2,4 @@ pub mod battery_history;
 pub mod event_pipeline;
 pub mod events;
 pub mod state_history;
+pub mod watcher_state;
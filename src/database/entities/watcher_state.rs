@@ -0,0 +1,121 @@
+use crate::database::DbResult;
+use crate::ups::{DeviceId, DeviceState, models::DevicePowerState};
+use sea_orm::entity::prelude::*;
+use sea_orm::{ActiveValue::Set, DatabaseConnection, sea_query::OnConflict};
+use serde::Serialize;
+
+pub type WatcherStateModel = Model;
+pub type WatcherStateActiveModel = ActiveModel;
+pub type WatcherStateEntity = Entity;
+
+/// Last state seen by the watcher for a device, restored when the
+/// service starts so conditions that were already active don't
+/// trigger events again
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Serialize)]
+#[sea_orm(table_name = "watcher_state")]
+pub struct Model {
+    /// ID of the device the state is for
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub device_id: DeviceId,
+
+    /// The last device state
+    pub state: DeviceState,
+
+    /// Power state that events were last emitted for, differs from the
+    /// device state while a transition is being debounced or held off
+    pub power_state: Option<DevicePowerState>,
+
+    /// Whether the device output was overloaded
+    pub overloaded: bool,
+
+    /// When the state was last updated
+    pub updated_at: DateTimeUtc,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Model {
+    /// Stores the state for the device replacing any existing state
+    pub async fn save(
+        db: &DatabaseConnection,
+        device_id: DeviceId,
+        state: DeviceState,
+        power_state: Option<DevicePowerState>,
+        overloaded: bool,
+        updated_at: DateTimeUtc,
+    ) -> DbResult<()> {
+        Entity::insert(ActiveModel {
+            device_id: Set(device_id),
+            state: Set(state),
+            power_state: Set(power_state),
+            overloaded: Set(overloaded),
+            updated_at: Set(updated_at),
+        })
+        .on_conflict(
+            OnConflict::column(Column::DeviceId)
+                .update_columns([
+                    Column::State,
+                    Column::PowerState,
+                    Column::Overloaded,
+                    Column::UpdatedAt,
+                ])
+                .to_owned(),
+        )
+        .exec(db)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Gets the stored state for the device
+    pub async fn get(db: &DatabaseConnection, device_id: &str) -> DbResult<Option<Self>> {
+        Entity::find_by_id(device_id).one(db).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+
+    use super::WatcherStateModel;
+    use crate::{
+        database::connect_database,
+        ups::{Protocol, models::DevicePowerState::Utility, registry::DEFAULT_DEVICE_ID},
+    };
+
+    /// Saving should replace the previously stored state
+    #[tokio::test]
+    async fn test_save_state() {
+        let db = connect_database("sqlite::memory:").await;
+
+        let utility = Protocol::Qs
+            .parse_state("(208.4 140.0 208.4 034 59.9 2.05 35.0 00000000".into())
+            .unwrap();
+        let battery = Protocol::Qs
+            .parse_state("(208.4 140.0 208.4 034 59.9 2.05 35.0 10000000".into())
+            .unwrap();
+
+        for (state, power_state) in [(utility, Some(Utility)), (battery.clone(), Some(Utility))] {
+            WatcherStateModel::save(
+                &db,
+                DEFAULT_DEVICE_ID.to_string(),
+                state,
+                power_state,
+                false,
+                Utc::now(),
+            )
+            .await
+            .unwrap();
+        }
+
+        let saved = WatcherStateModel::get(&db, DEFAULT_DEVICE_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(saved.state, battery);
+        assert_eq!(saved.power_state, Some(Utility));
+    }
+}
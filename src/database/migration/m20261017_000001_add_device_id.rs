@@ -0,0 +1,175 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+/// Device ID assigned to history recorded before multiple devices were supported
+const DEFAULT_DEVICE_ID: &str = "default";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only supports a single alteration per statement
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Events::Table)
+                    .add_column(string(Events::DeviceId).default(DEFAULT_DEVICE_ID))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(StateHistory::Table)
+                    .add_column(string(StateHistory::DeviceId).default(DEFAULT_DEVICE_ID))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(BatteryHistory::Table)
+                    .add_column(string(BatteryHistory::DeviceId).default(DEFAULT_DEVICE_ID))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Pipelines without a device run for any device
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(EventPipelines::Table)
+                    .add_column(string_null(EventPipelines::DeviceId))
+                    .to_owned(),
+            )
+            .await?;
+
+        // Create a index over the device IDs
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-event-device-id")
+                    .table(Events::Table)
+                    .col(Events::DeviceId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-state-history-device-id")
+                    .table(StateHistory::Table)
+                    .col(StateHistory::DeviceId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-battery-history-device-id")
+                    .table(BatteryHistory::Table)
+                    .col(BatteryHistory::DeviceId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-event-pl-device-id")
+                    .table(EventPipelines::Table)
+                    .col(EventPipelines::DeviceId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Indexes must be removed before their columns can be dropped
+        for (name, table) in [
+            ("idx-event-device-id", Events::Table.into_iden()),
+            (
+                "idx-state-history-device-id",
+                StateHistory::Table.into_iden(),
+            ),
+            (
+                "idx-battery-history-device-id",
+                BatteryHistory::Table.into_iden(),
+            ),
+            ("idx-event-pl-device-id", EventPipelines::Table.into_iden()),
+        ] {
+            manager
+                .drop_index(Index::drop().name(name).table(table).to_owned())
+                .await?;
+        }
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Events::Table)
+                    .drop_column(Events::DeviceId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(StateHistory::Table)
+                    .drop_column(StateHistory::DeviceId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(BatteryHistory::Table)
+                    .drop_column(BatteryHistory::DeviceId)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(EventPipelines::Table)
+                    .drop_column(EventPipelines::DeviceId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Events {
+    Table,
+    DeviceId,
+}
+
+#[derive(DeriveIden)]
+enum StateHistory {
+    Table,
+    DeviceId,
+}
+
+#[derive(DeriveIden)]
+enum BatteryHistory {
+    Table,
+    DeviceId,
+}
+
+#[derive(DeriveIden)]
+enum EventPipelines {
+    Table,
+    DeviceId,
+}
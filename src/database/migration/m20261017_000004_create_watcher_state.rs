@@ -0,0 +1,37 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(WatcherState::Table)
+                    .if_not_exists()
+                    .col(string(WatcherState::DeviceId).primary_key())
+                    .col(json(WatcherState::State))
+                    .col(boolean(WatcherState::Overloaded))
+                    .col(date_time(WatcherState::UpdatedAt))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(WatcherState::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum WatcherState {
+    Table,
+    DeviceId,
+    State,
+    Overloaded,
+    UpdatedAt,
+}
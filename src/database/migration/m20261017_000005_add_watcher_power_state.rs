@@ -0,0 +1,36 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Power state events were last emitted for, null for states saved before this migration
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(WatcherState::Table)
+                    .add_column(json_null(WatcherState::PowerState))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(WatcherState::Table)
+                    .drop_column(WatcherState::PowerState)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum WatcherState {
+    Table,
+    PowerState,
+}
@@ -4,6 +4,11 @@ mod m20240705_091507_create_events;
 mod m20240706_034731_create_battery_history;
 mod m20240706_034731_create_state_history;
 mod m20240709_071553_create_event_pipelines;
+mod m20261017_000001_add_device_id;
+mod m20261017_000002_add_event_battery_test;
+mod m20261017_000003_add_event_snapshot;
+mod m20261017_000004_create_watcher_state;
+mod m20261017_000005_add_watcher_power_state;
 
 pub struct Migrator;
 
@@ -15,6 +20,11 @@ impl MigratorTrait for Migrator {
             Box::new(m20240706_034731_create_battery_history::Migration),
             Box::new(m20240706_034731_create_state_history::Migration),
             Box::new(m20240709_071553_create_event_pipelines::Migration),
+            Box::new(m20261017_000001_add_device_id::Migration),
+            Box::new(m20261017_000002_add_event_battery_test::Migration),
+            Box::new(m20261017_000003_add_event_snapshot::Migration),
+            Box::new(m20261017_000004_create_watcher_state::Migration),
+            Box::new(m20261017_000005_add_watcher_power_state::Migration),
         ]
     }
 }
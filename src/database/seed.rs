@@ -1,6 +1,9 @@
 use chrono::Utc;
 
-use crate::database::entities::events::{EventModel, UPSEvent};
+use crate::{
+    database::entities::events::{EventModel, UPSEvent},
+    ups::registry::DEFAULT_DEVICE_ID,
+};
 
 /// Seeds 50 AC failure events into the database
 #[tokio::test]
@@ -12,13 +15,20 @@ async fn seed_ac() {
 
     for _ in 0..50 {
         let date = Utc::now();
-        EventModel::create(&db, UPSEvent::ACFailure, date)
-            .await
-            .unwrap();
+        EventModel::create(
+            &db,
+            DEFAULT_DEVICE_ID.to_string(),
+            UPSEvent::ACFailure,
+            None,
+            None,
+            date,
+        )
+        .await
+        .unwrap();
     }
 
     let end = Utc::now();
 
-    let events = EventModel::get_range(&db, start, end).await.unwrap();
+    let events = EventModel::get_range(&db, None, start, end).await.unwrap();
     dbg!(events);
 }
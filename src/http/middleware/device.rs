@@ -0,0 +1,46 @@
+use crate::{
+    http::error::{DynHttpError, HttpError},
+    ups::{DeviceId, SharedDeviceRegistry, SharedSimulation},
+};
+use axum::{
+    Extension,
+    extract::{Path, Request},
+    middleware::Next,
+    response::Response,
+};
+use reqwest::StatusCode;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[error("Unknown device: {0}")]
+pub struct UnknownDeviceError(pub DeviceId);
+
+impl HttpError for UnknownDeviceError {
+    fn status(&self) -> StatusCode {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Middleware for device specific routes, replaces the executor and simulation
+/// extensions with the ones for the device from the `device_id` path parameter
+pub async fn select_device(
+    Extension(registry): Extension<SharedDeviceRegistry>,
+    Path(device_id): Path<DeviceId>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, DynHttpError> {
+    let device = registry
+        .get(&device_id)
+        .ok_or(UnknownDeviceError(device_id))?;
+
+    let extensions = request.extensions_mut();
+    extensions.insert(device.executor.clone());
+
+    // Replace the primary device simulation
+    extensions.remove::<SharedSimulation>();
+    if let Some(simulation) = device.simulation.clone() {
+        extensions.insert(simulation);
+    }
+
+    Ok(next.run(request).await)
+}
@@ -1 +1,2 @@
 pub mod auth_gate;
+pub mod device;
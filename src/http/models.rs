@@ -1,9 +1,13 @@
 use garde::Validate;
+use ordered_float::OrderedFloat;
 use sea_orm::prelude::DateTimeUtc;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::{
-    action::ActionPipeline, database::entities::events::UPSEvent, utils::validate::valid_range,
+    action::ActionPipeline,
+    database::entities::events::UPSEvent,
+    ups::{DeviceId, DeviceInfo, DeviceRating, PendingUPSShutdown, UPSShutdownRestore},
+    utils::validate::{is_valid_ups_shutdown_delay, valid_range},
 };
 
 #[derive(Debug, Serialize)]
@@ -11,12 +15,74 @@ pub struct ServerDetails {
     pub version: &'static str,
 }
 
+#[derive(Debug, Serialize)]
+pub struct DeviceInfoResponse {
+    /// Identity of the device
+    pub info: DeviceInfo,
+    /// Rated values of the device
+    pub rating: DeviceRating,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct ScheduleUPSShutdownRequest {
+    /// Delay in minutes before shutting down the UPS
+    #[garde(custom(is_valid_ups_shutdown_delay))]
+    pub delay_minutes: OrderedFloat<f32>,
+    /// What the UPS should do after shutting down
+    #[garde(dive)]
+    #[serde(default)]
+    pub restore: UPSShutdownRestore,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UPSShutdownStatus {
+    /// The pending shutdown
+    #[serde(flatten)]
+    pub shutdown: PendingUPSShutdown,
+    /// Seconds remaining until the UPS shuts down
+    pub remaining_seconds: i64,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct RawCommandRequest {
+    /// Command to send to the device
+    #[garde(length(min = 1, max = 32), pattern(r"^[\x20-\x7E]+$"))]
+    pub command: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RawCommandResponse {
+    /// Command that was sent to the device
+    pub command: String,
+    /// Unparsed response from the device
+    pub response: String,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct SimulationControl {
+    /// Whether utility power is available
+    #[garde(skip)]
+    pub ac_power: Option<bool>,
+    /// Percentage load on the UPS output
+    #[garde(inner(range(max = 100)))]
+    pub load_percent: Option<u8>,
+    /// Battery capacity percentage
+    #[garde(inner(range(max = 100)))]
+    pub capacity: Option<u8>,
+    /// Whether the UPS is in a fault state
+    #[garde(skip)]
+    pub fault: Option<bool>,
+}
+
 #[derive(Debug, Validate, Deserialize)]
 pub struct RangeQuery {
     #[garde(skip)]
     pub start: DateTimeUtc,
     #[garde(custom(valid_range(&self.start)))]
     pub end: DateTimeUtc,
+    /// Optional device to restrict the history to
+    #[garde(skip)]
+    pub device: Option<DeviceId>,
 }
 
 #[derive(Debug, Validate, Deserialize)]
@@ -25,6 +91,10 @@ pub struct CreateEventPipeline {
     pub name: String,
     #[garde(skip)]
     pub event: UPSEvent,
+    /// Device the pipeline is for, pipeline runs for all devices when missing
+    #[garde(skip)]
+    #[serde(default)]
+    pub device_id: Option<DeviceId>,
     #[garde(dive)]
     pub pipeline: ActionPipeline,
     #[garde(skip)]
@@ -37,6 +107,10 @@ pub struct UpdateEventPipeline {
     pub name: Option<String>,
     #[garde(skip)]
     pub event: Option<UPSEvent>,
+    /// Device the pipeline is for, a null value clears the device
+    #[garde(skip)]
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub device_id: Option<Option<DeviceId>>,
     #[garde(dive)]
     pub pipeline: Option<ActionPipeline>,
     #[garde(skip)]
@@ -55,3 +129,13 @@ pub struct LoginRequest {
 pub struct LoginStateResponse {
     pub logged_in: bool,
 }
+
+/// Deserializes a present value as [Some] allowing a missing field (default [None])
+/// to be distinguished from an explicit null value (`Some(None)`)
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
@@ -0,0 +1,14 @@
+use crate::{
+    http::error::HttpResult,
+    ups::{SharedDeviceRegistry, registry::DeviceDetails},
+};
+use axum::{Extension, Json};
+
+/// GET /api/devices
+///
+/// Requests the list of devices being monitored
+pub async fn get_devices(
+    Extension(registry): Extension<SharedDeviceRegistry>,
+) -> HttpResult<Vec<DeviceDetails>> {
+    Ok(Json(registry.details()))
+}
@@ -12,12 +12,13 @@ use sea_orm::DatabaseConnection;
 
 /// GET /api/history/battery-state
 ///
-/// Get the battery state history for the provided date range
+/// Get the battery state history for the provided date range, optionally
+/// for a specific device
 pub async fn battery_state_history(
     Extension(db): Extension<DatabaseConnection>,
-    Garde(Query(RangeQuery { start, end })): Garde<Query<RangeQuery>>,
+    Garde(Query(RangeQuery { start, end, device })): Garde<Query<RangeQuery>>,
 ) -> HttpResult<Vec<BatteryHistoryModel>> {
-    let history = BatteryHistoryModel::get_range(&db, start, end)
+    let history = BatteryHistoryModel::get_range(&db, device.as_deref(), start, end)
         .await
         .context("Failed to query battery history")?;
 
@@ -26,12 +27,13 @@ pub async fn battery_state_history(
 
 /// GET /api/history/device-state
 ///
-/// Get the device state history for the provided date range
+/// Get the device state history for the provided date range, optionally
+/// for a specific device
 pub async fn device_state_history(
     Extension(db): Extension<DatabaseConnection>,
-    Garde(Query(RangeQuery { start, end })): Garde<Query<RangeQuery>>,
+    Garde(Query(RangeQuery { start, end, device })): Garde<Query<RangeQuery>>,
 ) -> HttpResult<Vec<StateHistoryModel>> {
-    let history = StateHistoryModel::get_range(&db, start, end)
+    let history = StateHistoryModel::get_range(&db, device.as_deref(), start, end)
         .await
         .context("Failed to query state history")?;
 
@@ -40,12 +42,13 @@ pub async fn device_state_history(
 
 /// GET /api/history/event
 ///
-/// Get the device state history for the provided date range
+/// Get the event history for the provided date range, optionally for a
+/// specific device
 pub async fn event_history(
     Extension(db): Extension<DatabaseConnection>,
-    Garde(Query(RangeQuery { start, end })): Garde<Query<RangeQuery>>,
+    Garde(Query(RangeQuery { start, end, device })): Garde<Query<RangeQuery>>,
 ) -> HttpResult<Vec<EventModel>> {
-    let history = EventModel::get_range(&db, start, end)
+    let history = EventModel::get_range(&db, device.as_deref(), start, end)
         .await
         .context("Failed to query event history")?;
 
@@ -1,15 +1,18 @@
 use axum::{
-    Router,
+    Router, middleware,
     routing::{get, post},
 };
 
-use crate::ups::device::DefaultDevice;
+use crate::{http::middleware::device::select_device, ups::device::DefaultDevice};
 
 mod auth;
+mod devices;
 mod history;
 mod pipelines;
+mod raw;
 mod realtime;
 mod server;
+mod simulation;
 mod state;
 mod web;
 
@@ -19,10 +22,13 @@ pub fn router() -> Router {
             "/api",
             Router::new()
                 .route("/server", get(server::server_details))
-                .route("/device-state", get(state::device_state::<DefaultDevice>))
-                .route(
-                    "/battery-state",
-                    get(state::device_battery::<DefaultDevice>),
+                // Routes for the primary device
+                .merge(device_router())
+                .route("/devices", get(devices::get_devices))
+                // Routes for a specific device
+                .nest(
+                    "/devices/{device_id}",
+                    device_router().route_layer(middleware::from_fn(select_device)),
                 )
                 .route("/events", get(state::events))
                 .nest(
@@ -41,7 +47,7 @@ pub fn router() -> Router {
                                 .post(pipelines::create_event_pipeline),
                         )
                         .nest(
-                            "/:id",
+                            "/{id}",
                             Router::new()
                                 .route(
                                     "/",
@@ -55,13 +61,6 @@ pub fn router() -> Router {
                                 ),
                         ),
                 )
-                .route("/toggle-buzzer", post(realtime::toggle_buzzer))
-                .nest(
-                    "/test-battery",
-                    Router::new()
-                        .route("/start", post(realtime::test_battery_start))
-                        .route("/cancel", post(realtime::test_battery_cancel)),
-                )
                 .route("/login", post(auth::login))
                 .route("/login-state", get(auth::login_state))
                 .route("/logout", post(auth::logout)),
@@ -69,3 +68,54 @@ pub fn router() -> Router {
         // Public content fallback
         .fallback_service(web::PublicContent)
 }
+
+/// Routes that interact with a single device, uses the executor
+/// from the request extensions
+fn device_router() -> Router {
+    Router::new()
+        .route("/device-state", get(state::device_state::<DefaultDevice>))
+        .route(
+            "/battery-state",
+            get(state::device_battery::<DefaultDevice>),
+        )
+        .route("/device-info", get(state::device_info::<DefaultDevice>))
+        .route("/device/raw", post(raw::raw_command))
+        .route(
+            "/device/parse-failures",
+            get(state::parse_failures::<DefaultDevice>),
+        )
+        .route("/device/health", get(state::device_health::<DefaultDevice>))
+        .route("/toggle-buzzer", post(realtime::toggle_buzzer))
+        .nest(
+            "/test-battery",
+            Router::new()
+                .route(
+                    "/start",
+                    post(realtime::test_battery_start::<DefaultDevice>),
+                )
+                .route("/cancel", post(realtime::test_battery_cancel)),
+        )
+        .route(
+            "/simulation",
+            get(simulation::simulation_status).post(simulation::update_simulation),
+        )
+        .nest(
+            "/ups-shutdown",
+            Router::new()
+                .route("/", get(realtime::ups_shutdown_status))
+                .route("/schedule", post(realtime::ups_shutdown_schedule))
+                .route("/cancel", post(realtime::ups_shutdown_cancel)),
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use super::router;
+
+    /// Tests that the router can be constructed without any
+    /// conflicting or invalid routes
+    #[test]
+    fn test_router() {
+        _ = router();
+    }
+}
@@ -3,10 +3,10 @@ use crate::{
     database::entities::event_pipeline::{EventPipelineId, EventPipelineModel, ListEventPipeline},
     http::{
         error::{HttpResult, HttpStatusResult},
-        middleware::auth_gate::AuthGate,
+        middleware::{auth_gate::AuthGate, device::UnknownDeviceError},
         models::{CreateEventPipeline, UpdateEventPipeline},
     },
-    ups::{DeviceExecutorHandle, device::Device},
+    ups::{SharedDeviceRegistry, device::Device},
 };
 use anyhow::{Context, anyhow};
 use axum::extract::Path;
@@ -29,7 +29,7 @@ pub async fn get_event_pipelines(
     Ok(Json(event_pipelines))
 }
 
-/// GET /api/event-pipelines/:id
+/// GET /api/event-pipelines/{id}
 ///
 /// Requests a specific event pipeline
 pub async fn get_event_pipeline(
@@ -45,15 +45,22 @@ pub async fn get_event_pipeline(
     Ok(Json(event_pipeline))
 }
 
-/// PUT /api/event-pipelines/:id
+/// PUT /api/event-pipelines/{id}
 ///
 /// Updates a event pipeline
 pub async fn update_event_pipeline(
     _: AuthGate,
     Extension(db): Extension<DatabaseConnection>,
+    Extension(registry): Extension<SharedDeviceRegistry>,
     Path(id): Path<EventPipelineId>,
     Garde(Json(request)): Garde<Json<UpdateEventPipeline>>,
 ) -> HttpResult<EventPipelineModel> {
+    if let Some(Some(device_id)) = request.device_id.as_ref()
+        && registry.get(device_id).is_none()
+    {
+        return Err(UnknownDeviceError(device_id.clone()).into());
+    }
+
     let event_pipeline = EventPipelineModel::find_by_id(&db, id)
         .await
         .context("failed to find event pipeline")?
@@ -64,6 +71,7 @@ pub async fn update_event_pipeline(
             &db,
             request.name,
             request.event,
+            request.device_id,
             request.pipeline,
             request.cancellable,
             request.enabled,
@@ -80,13 +88,21 @@ pub async fn update_event_pipeline(
 pub async fn create_event_pipeline(
     _: AuthGate,
     Extension(db): Extension<DatabaseConnection>,
+    Extension(registry): Extension<SharedDeviceRegistry>,
     Garde(Json(request)): Garde<Json<CreateEventPipeline>>,
 ) -> HttpResult<EventPipelineModel> {
+    if let Some(device_id) = request.device_id.as_ref()
+        && registry.get(device_id).is_none()
+    {
+        return Err(UnknownDeviceError(device_id.clone()).into());
+    }
+
     let current_time = Utc::now();
     let event_pipeline = EventPipelineModel::create(
         &db,
         request.name,
         request.event,
+        request.device_id,
         request.pipeline,
         request.cancellable,
         current_time,
@@ -97,7 +113,7 @@ pub async fn create_event_pipeline(
     Ok(Json(event_pipeline))
 }
 
-/// DELETE /api/event-pipelines/:id
+/// DELETE /api/event-pipelines/{id}
 ///
 /// Deletes an event pipeline
 pub async fn delete_event_pipeline(
@@ -116,14 +132,17 @@ pub async fn delete_event_pipeline(
     Ok(StatusCode::OK)
 }
 
-/// POST /api/event-pipelines/:id/test
+/// POST /api/event-pipelines/{id}/test
 ///
 /// Tests a pipeline by running it once, does not run
 /// repeated actions and skips any delays
+///
+/// Runs against the pipeline device or the primary device when
+/// the pipeline is for any device
 pub async fn test_event_pipeline<D: Device>(
     _: AuthGate,
     Extension(db): Extension<DatabaseConnection>,
-    Extension(executor): Extension<DeviceExecutorHandle<D>>,
+    Extension(registry): Extension<SharedDeviceRegistry<D>>,
     Path(id): Path<EventPipelineId>,
 ) -> HttpStatusResult {
     let event_pipeline = EventPipelineModel::find_by_id(&db, id)
@@ -133,6 +152,14 @@ pub async fn test_event_pipeline<D: Device>(
 
     let event = event_pipeline.event;
 
+    let device = match event_pipeline.device_id.as_ref() {
+        Some(device_id) => registry
+            .get(device_id)
+            .ok_or_else(|| UnknownDeviceError(device_id.clone()))?,
+        None => registry.primary(),
+    };
+    let executor = device.executor.clone();
+
     tokio::spawn(async move {
         let executor = executor;
         let pipeline = event_pipeline;
@@ -0,0 +1,62 @@
+use crate::{
+    config::SharedConfig,
+    http::{
+        error::{HttpError, HttpResult},
+        middleware::auth_gate::AuthGate,
+        models::{RawCommandRequest, RawCommandResponse},
+    },
+    ups::{DeviceExecutorHandle, RawCommand},
+};
+use anyhow::Context;
+use axum::{Extension, Json};
+use axum_valid::Garde;
+use log::{info, warn};
+use reqwest::StatusCode;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[error("Command is not in the raw command allowlist")]
+pub struct RawCommandNotAllowed;
+
+impl HttpError for RawCommandNotAllowed {
+    fn log(&self) {}
+
+    fn status(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+}
+
+/// POST /api/device/raw
+///
+/// Sends a raw protocol command to the device and responds with the
+/// unparsed response. Only commands starting with one of the allowed
+/// prefixes from the config can be sent
+pub async fn raw_command(
+    _: AuthGate,
+    Extension(config): Extension<SharedConfig>,
+    Extension(executor): Extension<DeviceExecutorHandle>,
+    Garde(Json(request)): Garde<Json<RawCommandRequest>>,
+) -> HttpResult<RawCommandResponse> {
+    let command = request.command;
+
+    if !config.raw_commands.is_allowed(&command) {
+        warn!("rejected raw device command {command:?}, not in allowlist");
+        return Err(RawCommandNotAllowed.into());
+    }
+
+    info!("sending raw device command {command:?}");
+
+    let response = executor
+        .send(RawCommand {
+            command: command.as_str().into(),
+        })
+        .await
+        .context("raw command request")?;
+
+    info!("raw device command {command:?} responded with {response:?}");
+
+    Ok(Json(RawCommandResponse {
+        command,
+        response: response.to_string(),
+    }))
+}
@@ -1,10 +1,18 @@
-use crate::ups::{BatteryTest, CancelBatteryTest, DeviceExecutorHandle, ToggleBuzzer};
-use anyhow::Context;
-use axum::Extension;
+use crate::ups::{
+    BatteryTest, BatteryTestMinutes, BatteryTestMode, BatteryTestUntilLow, CancelBatteryTest,
+    CancelUPSShutdown, DeviceExecutorHandle, ScheduleUPSShutdown, ToggleBuzzer, device::Device,
+};
+use anyhow::{Context, anyhow};
+use axum::{Extension, Json, body::Bytes};
+use axum_valid::Garde;
+use chrono::Utc;
+use garde::Validate;
 use reqwest::StatusCode;
+use thiserror::Error;
 
-use crate::http::error::HttpStatusResult;
+use crate::http::error::{HttpError, HttpResult, HttpStatusResult};
 use crate::http::middleware::auth_gate::AuthGate;
+use crate::http::models::{ScheduleUPSShutdownRequest, UPSShutdownStatus};
 
 /// POST /api/toggle-buzzer
 ///
@@ -21,24 +29,51 @@ pub async fn toggle_buzzer(
     Ok(StatusCode::OK)
 }
 
+#[derive(Debug, Error)]
+#[error("Invalid battery test mode: {0}")]
+pub struct InvalidBatteryTestMode(String);
+
+impl HttpError for InvalidBatteryTestMode {
+    fn log(&self) {}
+
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+}
+
 /// POST /api/test-battery/start
 ///
-/// Starts a 10s battery test
-pub async fn test_battery_start(
+/// Starts a battery test, either a quick 10s test, a test for a number
+/// of minutes or a test until the battery is low. Requests without a
+/// body run the quick test
+pub async fn test_battery_start<D: Device>(
     _: AuthGate,
-    Extension(executor): Extension<DeviceExecutorHandle>,
+    Extension(executor): Extension<DeviceExecutorHandle<D>>,
+    body: Bytes,
 ) -> HttpStatusResult {
-    executor
-        .send(BatteryTest)
-        .await
-        .context("battery test request")?;
+    let mode = if body.iter().all(u8::is_ascii_whitespace) {
+        BatteryTestMode::Quick
+    } else {
+        let mode: BatteryTestMode =
+            serde_json::from_slice(&body).map_err(|err| InvalidBatteryTestMode(err.to_string()))?;
+        mode.validate()
+            .map_err(|err| InvalidBatteryTestMode(err.to_string()))?;
+        mode
+    };
+
+    match mode {
+        BatteryTestMode::Quick => executor.send(BatteryTest).await,
+        BatteryTestMode::Minutes { minutes } => executor.send(BatteryTestMinutes { minutes }).await,
+        BatteryTestMode::UntilLow => executor.send(BatteryTestUntilLow).await,
+    }
+    .context("battery test request")?;
 
     Ok(StatusCode::OK)
 }
 
 /// POST /api/test-battery/cancel
 ///
-/// Cancels a 10s battery test
+/// Cancels a running battery test
 pub async fn test_battery_cancel(
     _: AuthGate,
     Extension(executor): Extension<DeviceExecutorHandle>,
@@ -50,3 +85,99 @@ pub async fn test_battery_cancel(
 
     Ok(StatusCode::OK)
 }
+
+/// GET /api/ups-shutdown
+///
+/// Requests the pending UPS shutdown and the time remaining until
+/// the UPS shuts down, [None] when no shutdown is scheduled
+pub async fn ups_shutdown_status(
+    _: AuthGate,
+    Extension(executor): Extension<DeviceExecutorHandle>,
+) -> HttpResult<Option<UPSShutdownStatus>> {
+    let now = Utc::now();
+    let shutdown = executor
+        .control_state()
+        .lock()
+        .map_err(|_| anyhow!("control state poisoned"))?
+        .pending_ups_shutdown(now);
+
+    let status = shutdown.map(|shutdown| UPSShutdownStatus {
+        remaining_seconds: (shutdown.shutdown_at - now).num_seconds().max(0),
+        shutdown,
+    });
+
+    Ok(Json(status))
+}
+
+/// POST /api/ups-shutdown/schedule
+///
+/// Schedules a shutdown of the UPS after the requested delay
+pub async fn ups_shutdown_schedule(
+    _: AuthGate,
+    Extension(executor): Extension<DeviceExecutorHandle>,
+    Garde(Json(request)): Garde<Json<ScheduleUPSShutdownRequest>>,
+) -> HttpStatusResult {
+    executor
+        .send(ScheduleUPSShutdown {
+            delay_minutes: request.delay_minutes.0,
+            restore: request.restore,
+        })
+        .await
+        .context("schedule ups shutdown request")?;
+
+    Ok(StatusCode::OK)
+}
+
+/// POST /api/ups-shutdown/cancel
+///
+/// Cancels a pending UPS shutdown
+pub async fn ups_shutdown_cancel(
+    _: AuthGate,
+    Extension(executor): Extension<DeviceExecutorHandle>,
+) -> HttpStatusResult {
+    executor
+        .send(CancelUPSShutdown)
+        .await
+        .context("cancel ups shutdown request")?;
+
+    Ok(StatusCode::OK)
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{Extension, body::Bytes};
+
+    use super::test_battery_start;
+    use crate::{
+        http::middleware::auth_gate::AuthGate,
+        ups::{DeviceExecutor, MockDevice, MockDeviceCreator},
+    };
+
+    /// Requests without a body should run the quick battery test
+    #[tokio::test]
+    async fn test_battery_start_empty_body() {
+        let (device_creator, mut mock_handle) = MockDeviceCreator::new();
+        let executor = DeviceExecutor::<MockDevice>::start(device_creator);
+
+        mock_handle.next_response("(ACK".into());
+
+        test_battery_start(AuthGate, Extension(executor), Bytes::new())
+            .await
+            .expect("failed to start battery test");
+
+        let command = mock_handle.next_command().await;
+        assert_eq!(command, Some("T".into()));
+    }
+
+    /// Invalid test modes should be rejected
+    #[tokio::test]
+    async fn test_battery_start_invalid() {
+        let (device_creator, _mock_handle) = MockDeviceCreator::new();
+        let executor = DeviceExecutor::<MockDevice>::start(device_creator);
+
+        let body = Bytes::from_static(br#"{"mode":"minutes","minutes":0}"#);
+        test_battery_start(AuthGate, Extension(executor), body)
+            .await
+            .expect_err("minutes should be out of range");
+    }
+}
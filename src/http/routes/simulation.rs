@@ -0,0 +1,78 @@
+use crate::{
+    http::{
+        error::{DynHttpError, HttpError, HttpResult},
+        middleware::auth_gate::AuthGate,
+        models::SimulationControl,
+    },
+    ups::{SharedSimulation, device::SimulationStatus},
+};
+use anyhow::anyhow;
+use axum::{Extension, Json};
+use axum_valid::Garde;
+use reqwest::StatusCode;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[error("Device is not simulated")]
+pub struct NotSimulatedError;
+
+impl HttpError for NotSimulatedError {
+    fn status(&self) -> StatusCode {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Gets the simulation from the optional extension
+fn require_simulation(
+    simulation: Option<Extension<SharedSimulation>>,
+) -> Result<SharedSimulation, DynHttpError> {
+    simulation
+        .map(|Extension(simulation)| simulation)
+        .ok_or_else(|| NotSimulatedError.into())
+}
+
+/// GET /api/simulation
+///
+/// Requests the current state of the simulated device
+pub async fn simulation_status(
+    simulation: Option<Extension<SharedSimulation>>,
+) -> HttpResult<SimulationStatus> {
+    let simulation = require_simulation(simulation)?;
+    let mut simulation = simulation
+        .lock()
+        .map_err(|_| anyhow!("simulation poisoned"))?;
+
+    Ok(Json(simulation.status()))
+}
+
+/// POST /api/simulation
+///
+/// Steers the simulated device, only the provided values are changed
+pub async fn update_simulation(
+    _: AuthGate,
+    simulation: Option<Extension<SharedSimulation>>,
+    Garde(Json(request)): Garde<Json<SimulationControl>>,
+) -> HttpResult<SimulationStatus> {
+    let simulation = require_simulation(simulation)?;
+    let mut simulation = simulation
+        .lock()
+        .map_err(|_| anyhow!("simulation poisoned"))?;
+
+    if let Some(ac_power) = request.ac_power {
+        simulation.set_ac_power(ac_power);
+    }
+
+    if let Some(load_percent) = request.load_percent {
+        simulation.set_load_percent(load_percent);
+    }
+
+    if let Some(capacity) = request.capacity {
+        simulation.set_capacity(capacity);
+    }
+
+    if let Some(fault) = request.fault {
+        simulation.set_fault(fault);
+    }
+
+    Ok(Json(simulation.status()))
+}
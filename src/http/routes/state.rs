@@ -1,11 +1,14 @@
 use crate::{
-    http::error::HttpResult,
+    http::{error::HttpResult, models::DeviceInfoResponse},
     services::watcher::UPSWatcherHandle,
     ups::{
-        DeviceBattery, DeviceExecutorHandle, DeviceState, QueryDeviceBattery, QueryDeviceState,
+        DeviceBattery, DeviceExecutorHandle, DeviceState, QueryDeviceBattery, QueryDeviceInfo,
+        QueryDeviceRating, QueryDeviceState,
         device::Device,
+        diagnostics::{DeviceHealth, ParseFailures},
     },
 };
+use anyhow::anyhow;
 use axum::{
     Extension, Json,
     response::{
@@ -14,6 +17,7 @@ use axum::{
     },
 };
 use futures::Stream;
+use std::collections::HashMap;
 use std::convert::Infallible;
 use std::time::Duration;
 use tokio_stream::StreamExt;
@@ -40,6 +44,52 @@ pub async fn device_battery<D: Device>(
     Ok(Json(battery))
 }
 
+/// GET /api/device-info
+///
+/// Requests the identity (company, model, version) and rated
+/// values of the device
+pub async fn device_info<D: Device>(
+    Extension(executor): Extension<DeviceExecutorHandle<D>>,
+) -> HttpResult<DeviceInfoResponse> {
+    let info = executor.send(QueryDeviceInfo).await?;
+    let rating = executor.send(QueryDeviceRating).await?;
+
+    Ok(Json(DeviceInfoResponse { info, rating }))
+}
+
+/// GET /api/device/parse-failures
+///
+/// Requests the number of responses that failed to parse for each
+/// command along with the last response that failed to parse
+pub async fn parse_failures<D: Device>(
+    Extension(executor): Extension<DeviceExecutorHandle<D>>,
+) -> HttpResult<HashMap<&'static str, ParseFailures>> {
+    let parse_failures = executor
+        .diagnostics()
+        .lock()
+        .map_err(|_| anyhow!("diagnostics poisoned"))?
+        .parse_failures
+        .clone();
+
+    Ok(Json(parse_failures))
+}
+
+/// GET /api/device/health
+///
+/// Requests statistics about the communication with the device for
+/// each command along with the number of reconnects
+pub async fn device_health<D: Device>(
+    Extension(executor): Extension<DeviceExecutorHandle<D>>,
+) -> HttpResult<DeviceHealth> {
+    let health = executor
+        .diagnostics()
+        .lock()
+        .map_err(|_| anyhow!("diagnostics poisoned"))?
+        .health(executor.is_connected());
+
+    Ok(Json(health))
+}
+
 /// GET /api/events
 ///
 /// SSE events endpoint
@@ -48,12 +98,8 @@ pub async fn events(
 ) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
     let stream = watcher_handle
         .into_stream()
-        .map(|result| {
-            let event = result?;
-            Ok::<Event, anyhow::Error>(Event::default().json_data(event)?)
-        })
-        // Filter out actual failures
-        .filter_map(|result| result.ok())
+        // Filter out events that failed to serialize
+        .filter_map(|event| Event::default().json_data(event).ok())
         // Include responses
         .map(Ok)
         .throttle(Duration::from_secs(1));
@@ -65,7 +111,7 @@ pub async fn events(
 mod test {
     use axum::Extension;
 
-    use super::{device_battery, device_state};
+    use super::{device_battery, device_info, device_state, parse_failures};
     use crate::ups::{DeviceExecutor, MockDevice, MockDeviceCreator};
 
     /// Tests that the device_state endpoint executes the correct command
@@ -73,7 +119,7 @@ mod test {
     #[tokio::test]
     async fn test_device_state() {
         let (device_creator, mut mock_handle) = MockDeviceCreator::new();
-        let executor = DeviceExecutor::<MockDevice>::start(device_creator).unwrap();
+        let executor = DeviceExecutor::<MockDevice>::start(device_creator);
 
         // Set the battery response
         mock_handle.next_response("(237.1 237.1 237.1 008 50.1 27.1 --.- 00001001".into());
@@ -94,7 +140,7 @@ mod test {
     #[tokio::test]
     async fn test_device_battery() {
         let (device_creator, mut mock_handle) = MockDeviceCreator::new();
-        let executor = DeviceExecutor::<MockDevice>::start(device_creator).unwrap();
+        let executor = DeviceExecutor::<MockDevice>::start(device_creator);
 
         // Set the battery response
         mock_handle.next_response("(100 02832 50.0 000.5 175 290 0 0000020000112000".into());
@@ -109,4 +155,53 @@ mod test {
         // Ensure expected command was executed
         assert_eq!(command, Some("QI".into()));
     }
+
+    /// Tests that the device_info endpoint executes the info and rating
+    /// commands and provides a success response for valid device responses
+    #[tokio::test]
+    async fn test_device_info() {
+        let (device_creator, mut mock_handle) = MockDeviceCreator::new();
+        let executor = DeviceExecutor::<MockDevice>::start(device_creator);
+
+        // Set the info and rating responses
+        mock_handle.next_response("#Dynamix         UPSD2000   V1.0      ".into());
+        mock_handle.next_response("#220.0 000 024.0 50.0".into());
+
+        _ = device_info(Extension(executor))
+            .await
+            .expect("failed to get device info");
+
+        // Ensure expected commands were executed
+        assert_eq!(mock_handle.next_command().await, Some("I".into()));
+        assert_eq!(mock_handle.next_command().await, Some("F".into()));
+    }
+
+    /// Tests that responses which fail to parse are counted and the
+    /// last failed response is provided by the parse_failures endpoint
+    #[tokio::test]
+    async fn test_parse_failures() {
+        let (device_creator, mock_handle) = MockDeviceCreator::new();
+        let executor = DeviceExecutor::<MockDevice>::start(device_creator);
+
+        mock_handle.next_response("(A 02832".into());
+        mock_handle.next_response("(--- 02832".into());
+
+        for _ in 0..2 {
+            device_battery(Extension(executor.clone()))
+                .await
+                .expect_err("battery should fail parsing");
+        }
+
+        let failures = parse_failures(Extension(executor))
+            .await
+            .expect("failed to get parse failures");
+        let failures = failures
+            .get("device_battery")
+            .expect("missing device battery failures");
+
+        assert_eq!(failures.count, 2);
+        assert_eq!(failures.last_failure.response, "(--- 02832");
+        assert_eq!(failures.last_failure.field, "capacity");
+        assert_eq!(failures.last_failure.index, 0);
+    }
 }
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use anyhow::Context;
 use clap::{Parser, Subcommand};
 use rust_i18n::i18n;
@@ -7,6 +9,7 @@ pub mod config;
 pub mod database;
 pub mod http;
 pub mod logging;
+pub mod nut;
 pub mod server;
 pub mod services;
 pub mod ups;
@@ -20,6 +23,14 @@ i18n!("locales", fallback = "en");
 struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
+
+    /// Use simulated UPS devices instead of the configured devices
+    #[arg(long)]
+    simulate: bool,
+
+    /// Replay a device capture file instead of using the configured devices
+    #[arg(long, value_name = "FILE", conflicts_with = "simulate")]
+    replay: Option<PathBuf>,
 }
 
 #[derive(Subcommand)]
@@ -27,6 +38,25 @@ enum Commands {
     /// Interact with the oguard system service
     #[cfg(windows)]
     Service(ServiceArgs),
+    /// Find UPS devices connected to this machine
+    Devices(DevicesArgs),
+}
+
+#[derive(Debug, clap::Args)]
+#[command(args_conflicts_with_subcommands = true)]
+struct DevicesArgs {
+    #[command(subcommand)]
+    command: DevicesCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum DevicesCommands {
+    /// List the connected HID devices matching the configured vendor and product IDs
+    List {
+        /// List every connected HID device
+        #[arg(long)]
+        all: bool,
+    },
 }
 
 #[cfg(windows)]
@@ -75,6 +105,11 @@ fn main() -> anyhow::Result<()> {
                 ServiceCommands::Restart => utils::windows_service::restart_service(),
                 ServiceCommands::Delete => utils::windows_service::delete_service(),
             },
+            Commands::Devices(devices) => match devices.command {
+                DevicesCommands::List { all } => {
+                    utils::devices::list_devices(&config.device.hid, all)
+                }
+            },
         };
     }
 
@@ -83,7 +118,7 @@ fn main() -> anyhow::Result<()> {
         // Debug builds run the server directly
         #[cfg(debug_assertions)]
         {
-            server_main()?;
+            server_main(cli.simulate, cli.replay)?;
         }
 
         // Production builds start the service logic
@@ -99,7 +134,7 @@ fn main() -> anyhow::Result<()> {
 
     #[cfg(unix)]
     {
-        server_main()?;
+        server_main(cli.simulate, cli.replay)?;
     }
 
     Ok(())
@@ -121,9 +156,17 @@ extern "system" fn ffi_service_main(num_service_arguments: u32, service_argument
 /// When running a debug build or a unix build we run the server
 /// directly without any of the windows service code
 #[cfg(any(debug_assertions, unix))]
-fn server_main() -> anyhow::Result<()> {
+fn server_main(simulate: bool, replay: Option<PathBuf>) -> anyhow::Result<()> {
     // Load the configuration
-    let config = config::load_user();
+    let mut config = config::load_user();
+
+    if simulate {
+        config.simulate_devices();
+    }
+
+    if let Some(replay) = replay {
+        config.replay_device(replay);
+    }
 
     // Setup logging
     logging::setup(&config.logging, true).context("failed to setup logging")?;
@@ -0,0 +1,102 @@
+//! # NUT
+//!
+//! Support for the NUT (Network UPS Tools) network protocol, allows oguard to
+//! act as a `upsd` server for existing NUT clients (upsmon, exporters, etc).
+//!
+//! The protocol is line based, each line is a command made up of space
+//! separated arguments which may be quoted using double quotes with
+//! backslash escapes
+
+pub mod server;
+
+/// Default port for the NUT network protocol
+pub const DEFAULT_NUT_PORT: u16 = 3493;
+
+/// Splits a protocol line into its arguments, arguments are separated by
+/// whitespace and may be quoted to include spaces. Within quoted arguments
+/// backslash escapes the next character.
+///
+/// Returns [None] if the line contains an unterminated quote
+pub fn parse_line(line: &str) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        // Skip leading whitespace
+        while chars.next_if(|value| value.is_whitespace()).is_some() {}
+
+        let Some(first) = chars.next() else {
+            return Some(args);
+        };
+
+        let mut arg = String::new();
+
+        if first == '"' {
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => arg.push(chars.next()?),
+                    value => arg.push(value),
+                }
+            }
+        } else {
+            arg.push(first);
+
+            while let Some(value) = chars.next_if(|value| !value.is_whitespace()) {
+                arg.push(value);
+            }
+        }
+
+        args.push(arg);
+    }
+}
+
+/// Quotes a value for use in a protocol line escaping any quotes
+/// and backslashes within the value
+pub fn quote(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+
+    for value in value.chars() {
+        if matches!(value, '"' | '\\') {
+            out.push('\\');
+        }
+
+        out.push(value);
+    }
+
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_line, quote};
+
+    /// Lines should split on whitespace and keep quoted arguments together
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(
+            parse_line("GET VAR default ups.status").unwrap(),
+            ["GET", "VAR", "default", "ups.status"]
+        );
+        assert_eq!(
+            parse_line("  PASSWORD \"pass word\"  ").unwrap(),
+            ["PASSWORD", "pass word"]
+        );
+        assert_eq!(
+            parse_line(r#"PASSWORD "a \"quoted\" \\ value""#).unwrap(),
+            ["PASSWORD", r#"a "quoted" \ value"#]
+        );
+        assert!(parse_line("").unwrap().is_empty());
+        assert!(parse_line("PASSWORD \"unterminated").is_none());
+    }
+
+    /// Quoted values should parse back to the original value
+    #[test]
+    fn test_quote() {
+        let value = r#"a "quoted" \ value"#;
+        let line = format!("VAR {}", quote(value));
+        assert_eq!(parse_line(&line).unwrap(), ["VAR", value]);
+    }
+}
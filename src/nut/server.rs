@@ -0,0 +1,654 @@
+//! # NUT Server
+//!
+//! Read-only `upsd` compatible server, exposes each device in the
+//! [SharedDeviceRegistry] as a NUT UPS named by its [DeviceId] with the
+//! device state and battery mapped onto the standard NUT variables.
+//!
+//! Clients may log in and claim primary status like they would with upsd,
+//! the primary client can set the forced shutdown (FSD) flag which is
+//! reported through `ups.status` so secondary clients begin shutting down
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use log::{debug, error, info};
+use strum::Display;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+use super::{parse_line, quote};
+use crate::{
+    config::NutConfig,
+    ups::{
+        DeviceBattery, DeviceId, DeviceInfo, DeviceLineType, DevicePowerState, DeviceRating,
+        DeviceState, QueryDeviceBattery, QueryDeviceInfo, QueryDeviceRating, QueryDeviceState,
+        SharedDeviceRegistry, WorkMode,
+    },
+};
+
+/// Version of the NUT network protocol implemented
+const NETWORK_PROTOCOL_VERSION: &str = "1.3";
+
+/// Maximum length of a single line from a client
+const MAX_LINE_LENGTH: u64 = 1024;
+
+/// Shared reference to a [NutServer]
+type SharedNutServer = Arc<NutServer>;
+
+/// Server state shared between all the connected clients
+pub struct NutServer {
+    /// Registry of devices to expose
+    registry: SharedDeviceRegistry,
+    /// Username clients must log in with
+    username: String,
+    /// Password clients must log in with, logins are denied when not set
+    password: Option<String>,
+    /// Devices that have had the forced shutdown flag set
+    forced_shutdown: Mutex<HashSet<DeviceId>>,
+    /// Number of clients logged in to each device
+    logins: Mutex<HashMap<DeviceId, usize>>,
+}
+
+/// Errors that can be sent to a client, sent as `ERR <ERROR>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "SCREAMING-KEBAB-CASE")]
+enum NutError {
+    AccessDenied,
+    UnknownUps,
+    VarNotSupported,
+    CmdNotSupported,
+    InvalidArgument,
+    UnknownCommand,
+    UsernameRequired,
+    PasswordRequired,
+    AlreadyLoggedIn,
+    AlreadySetUsername,
+    AlreadySetPassword,
+    DataStale,
+    DriverNotConnected,
+    FeatureNotConfigured,
+    Readonly,
+}
+
+/// Reply to a client command
+#[derive(Debug, PartialEq, Eq)]
+enum NutReply {
+    /// Lines to send to the client
+    Text(String),
+    /// Client has logged out and the connection should be closed
+    Logout,
+}
+
+impl NutServer {
+    /// Starts the server listening on the configured address
+    pub fn start(config: &NutConfig, registry: SharedDeviceRegistry) -> JoinHandle<()> {
+        let address = SocketAddr::new(config.host, config.port);
+        let server = Arc::new(Self::new(config, registry));
+
+        tokio::spawn(async move {
+            if let Err(err) = server.listen(address).await {
+                error!("failed to run nut server: {err:#}");
+            }
+        })
+    }
+
+    fn new(config: &NutConfig, registry: SharedDeviceRegistry) -> Self {
+        Self {
+            registry,
+            username: config.username.clone(),
+            password: config.password.clone(),
+            forced_shutdown: Default::default(),
+            logins: Default::default(),
+        }
+    }
+
+    /// Accepts client connections on the provided address
+    async fn listen(self: SharedNutServer, address: SocketAddr) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(address).await?;
+
+        info!("nut server listening on {address}");
+
+        loop {
+            let (stream, address) = listener.accept().await?;
+            let server = self.clone();
+
+            tokio::spawn(async move {
+                if let Err(err) = server.handle_client(stream).await {
+                    debug!("nut client {address} disconnected: {err}");
+                }
+            });
+        }
+    }
+
+    /// Handles the commands from a connected client until it logs
+    /// out or disconnects
+    async fn handle_client(self: SharedNutServer, stream: TcpStream) -> std::io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let mut session = NutSession::new(self);
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+
+            let count = (&mut reader)
+                .take(MAX_LINE_LENGTH)
+                .read_line(&mut line)
+                .await?;
+
+            if count == 0 {
+                return Ok(());
+            }
+
+            // Reject lines that are too long
+            if !line.ends_with('\n') && count as u64 == MAX_LINE_LENGTH {
+                let reply = format!("ERR {}\n", NutError::InvalidArgument);
+                writer.write_all(reply.as_bytes()).await?;
+                return Ok(());
+            }
+
+            let reply = match parse_line(&line) {
+                Some(args) if args.is_empty() => continue,
+                Some(args) => session.handle(&args).await,
+                None => Err(NutError::InvalidArgument),
+            };
+
+            match reply {
+                Ok(NutReply::Text(text)) => writer.write_all(text.as_bytes()).await?,
+                Ok(NutReply::Logout) => {
+                    writer.write_all(b"OK Goodbye\n").await?;
+                    return Ok(());
+                }
+                Err(err) => {
+                    let reply = format!("ERR {err}\n");
+                    writer.write_all(reply.as_bytes()).await?
+                }
+            }
+        }
+    }
+
+    /// Description for a device
+    fn description(&self, device_id: &str) -> String {
+        format!("OGuard UPS {device_id}")
+    }
+
+    /// Loads the current NUT variables for a device
+    async fn variables(&self, device_id: &str) -> Result<Vec<(&'static str, String)>, NutError> {
+        let device = self.registry.get(device_id).ok_or(NutError::UnknownUps)?;
+        let executor = &device.executor;
+
+        if !executor.is_open() {
+            return Err(NutError::DriverNotConnected);
+        }
+
+        let state = executor
+            .send(QueryDeviceState)
+            .await
+            .map_err(|_| NutError::DataStale)?;
+        let battery = executor
+            .send(QueryDeviceBattery)
+            .await
+            .map_err(|_| NutError::DataStale)?;
+
+        // Not all devices support the info and rating queries
+        let info = executor.send(QueryDeviceInfo).await.ok();
+        let rating = executor.send(QueryDeviceRating).await.ok();
+
+        let forced_shutdown = self
+            .forced_shutdown
+            .lock()
+            .is_ok_and(|forced_shutdown| forced_shutdown.contains(device_id));
+
+        Ok(device_variables(
+            &state,
+            &battery,
+            info.as_ref(),
+            rating.as_ref(),
+            forced_shutdown,
+        ))
+    }
+}
+
+/// State for a single client connection
+struct NutSession {
+    /// The server the client is connected to
+    server: SharedNutServer,
+    /// Username provided by the client
+    username: Option<String>,
+    /// Password provided by the client
+    password: Option<String>,
+    /// Device the client has logged in to
+    login: Option<DeviceId>,
+}
+
+impl NutSession {
+    fn new(server: SharedNutServer) -> Self {
+        Self {
+            server,
+            username: None,
+            password: None,
+            login: None,
+        }
+    }
+
+    /// Checks whether the client has provided the configured credentials
+    fn is_authorized(&self) -> bool {
+        let Some(password) = self.server.password.as_ref() else {
+            return false;
+        };
+
+        self.username
+            .as_ref()
+            .is_some_and(|username| username.eq(&self.server.username))
+            && self
+                .password
+                .as_ref()
+                .is_some_and(|value| value.eq(password))
+    }
+
+    /// Ensures the client is authorized and the device exists
+    fn authorized_device(&self, device_id: &str) -> Result<(), NutError> {
+        if !self.is_authorized() {
+            return Err(NutError::AccessDenied);
+        }
+
+        if self.server.registry.get(device_id).is_none() {
+            return Err(NutError::UnknownUps);
+        }
+
+        Ok(())
+    }
+
+    /// Handles a command from the client
+    async fn handle(&mut self, args: &[String]) -> Result<NutReply, NutError> {
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+        let text = match args.as_slice() {
+            ["VER"] => format!("OGuard {} (NUT compatible)\n", crate::VERSION),
+            ["NETVER"] => format!("{NETWORK_PROTOCOL_VERSION}\n"),
+            ["HELP"] => "Commands: HELP VER GET LIST LOGIN LOGOUT USERNAME PASSWORD PRIMARY FSD\n"
+                .to_string(),
+            ["STARTTLS"] => return Err(NutError::FeatureNotConfigured),
+            ["USERNAME", username] => {
+                if self.username.is_some() {
+                    return Err(NutError::AlreadySetUsername);
+                }
+
+                self.username = Some(username.to_string());
+                "OK\n".to_string()
+            }
+            ["PASSWORD", password] => {
+                if self.password.is_some() {
+                    return Err(NutError::AlreadySetPassword);
+                }
+
+                self.password = Some(password.to_string());
+                "OK\n".to_string()
+            }
+            ["LOGIN", device_id] => self.login(device_id)?,
+            ["LOGOUT"] => return Ok(NutReply::Logout),
+            [command @ ("PRIMARY" | "MASTER"), device_id] => {
+                self.authorized_device(device_id)?;
+                format!("OK {command}-GRANTED\n")
+            }
+            ["FSD", device_id] => {
+                self.authorized_device(device_id)?;
+
+                if let Ok(forced_shutdown) = &mut self.server.forced_shutdown.lock() {
+                    forced_shutdown.insert(device_id.to_string());
+                }
+
+                info!("nut client set forced shutdown for {device_id}");
+                "OK FSD-SET\n".to_string()
+            }
+            ["GET", "VAR", device_id, name] => {
+                let variables = self.server.variables(device_id).await?;
+                let (_, value) = variables
+                    .iter()
+                    .find(|(key, _)| key == name)
+                    .ok_or(NutError::VarNotSupported)?;
+
+                format!("VAR {device_id} {name} {}\n", quote(value))
+            }
+            ["GET", "UPSDESC", device_id] => {
+                self.server
+                    .registry
+                    .get(device_id)
+                    .ok_or(NutError::UnknownUps)?;
+
+                let description = self.server.description(device_id);
+                format!("UPSDESC {device_id} {}\n", quote(&description))
+            }
+            ["GET", "NUMLOGINS", device_id] => {
+                self.server
+                    .registry
+                    .get(device_id)
+                    .ok_or(NutError::UnknownUps)?;
+
+                let count = self
+                    .server
+                    .logins
+                    .lock()
+                    .ok()
+                    .and_then(|logins| logins.get(*device_id).copied())
+                    .unwrap_or_default();
+
+                format!("NUMLOGINS {device_id} {count}\n")
+            }
+            ["LIST", "UPS"] => {
+                let mut text = "BEGIN LIST UPS\n".to_string();
+                for device in self.server.registry.iter() {
+                    let description = self.server.description(&device.id);
+                    text.push_str(&format!("UPS {} {}\n", device.id, quote(&description)));
+                }
+                text.push_str("END LIST UPS\n");
+                text
+            }
+            ["LIST", "VAR", device_id] => {
+                let variables = self.server.variables(device_id).await?;
+
+                let mut text = format!("BEGIN LIST VAR {device_id}\n");
+                for (name, value) in variables {
+                    text.push_str(&format!("VAR {device_id} {name} {}\n", quote(&value)));
+                }
+                text.push_str(&format!("END LIST VAR {device_id}\n"));
+                text
+            }
+            // Commands and writable variables are not supported so the lists are empty
+            ["LIST", list @ ("CMD" | "RW"), device_id] => {
+                self.server
+                    .registry
+                    .get(device_id)
+                    .ok_or(NutError::UnknownUps)?;
+
+                format!("BEGIN LIST {list} {device_id}\nEND LIST {list} {device_id}\n")
+            }
+            ["SET", ..] => return Err(NutError::Readonly),
+            ["INSTCMD", ..] => return Err(NutError::CmdNotSupported),
+            ["GET" | "LIST", ..] => return Err(NutError::InvalidArgument),
+            _ => return Err(NutError::UnknownCommand),
+        };
+
+        Ok(NutReply::Text(text))
+    }
+
+    /// Logs the client in to a device
+    fn login(&mut self, device_id: &str) -> Result<String, NutError> {
+        if self.login.is_some() {
+            return Err(NutError::AlreadyLoggedIn);
+        }
+
+        if self.username.is_none() {
+            return Err(NutError::UsernameRequired);
+        }
+
+        if self.password.is_none() {
+            return Err(NutError::PasswordRequired);
+        }
+
+        self.authorized_device(device_id)?;
+
+        if let Ok(logins) = &mut self.server.logins.lock() {
+            *logins.entry(device_id.to_string()).or_default() += 1;
+        }
+
+        self.login = Some(device_id.to_string());
+        Ok("OK\n".to_string())
+    }
+}
+
+impl Drop for NutSession {
+    fn drop(&mut self) {
+        let Some(device_id) = self.login.take() else {
+            return;
+        };
+
+        if let Ok(logins) = &mut self.server.logins.lock()
+            && let Some(count) = logins.get_mut(&device_id)
+        {
+            *count = count.saturating_sub(1);
+        }
+    }
+}
+
+/// Creates the NUT `ups.status` value for a device state
+fn device_status(state: &DeviceState, forced_shutdown: bool) -> String {
+    let mut status = Vec::new();
+
+    if state.get_work_mode() == WorkMode::Standby {
+        status.push("OFF");
+    }
+
+    status.push(match state.device_power_state {
+        DevicePowerState::Utility => "OL",
+        DevicePowerState::Battery => "OB",
+    });
+
+    if state.battery_low {
+        status.push("LB");
+    }
+
+    if state.bypass_boost_active {
+        status.push("BOOST");
+    }
+
+    if state.battery_self_test {
+        status.push("CAL");
+    }
+
+    if state.fault_mode {
+        status.push("ALARM");
+    }
+
+    if forced_shutdown || state.shutdown_active {
+        status.push("FSD");
+    }
+
+    status.join(" ")
+}
+
+/// Maps the device state, battery, info and rating onto the standard
+/// NUT variables sorted by name
+fn device_variables(
+    state: &DeviceState,
+    battery: &DeviceBattery,
+    info: Option<&DeviceInfo>,
+    rating: Option<&DeviceRating>,
+    forced_shutdown: bool,
+) -> Vec<(&'static str, String)> {
+    let mut variables = vec![
+        ("device.type", "ups".to_string()),
+        ("driver.name", "oguard".to_string()),
+        ("driver.version", crate::VERSION.to_string()),
+        ("ups.status", device_status(state, forced_shutdown)),
+        ("ups.load", state.output_load_percent.to_string()),
+        (
+            "ups.type",
+            match state.device_line_type {
+                DeviceLineType::LineInteractive => "line-interactive",
+                DeviceLineType::OnLine => "online",
+            }
+            .to_string(),
+        ),
+        (
+            "ups.beeper.status",
+            if state.buzzer_control {
+                "enabled"
+            } else {
+                "disabled"
+            }
+            .to_string(),
+        ),
+        ("battery.charge", battery.capacity.to_string()),
+        ("battery.runtime", battery.remaining_time.to_string()),
+        ("battery.voltage", format!("{:.1}", state.battery_voltage)),
+        ("input.voltage", format!("{:.1}", state.input_voltage)),
+        ("output.voltage", format!("{:.1}", state.output_voltage)),
+        ("output.frequency", format!("{:.1}", state.output_frequency)),
+    ];
+
+    if let Some(input_fault_voltage) = state.input_fault_voltage {
+        variables.push(("input.voltage.fault", format!("{input_fault_voltage:.1}")));
+    }
+
+    if let Some(temperature) = state.temperature {
+        variables.push(("ups.temperature", format!("{temperature:.1}")));
+    }
+
+    if let Some(info) = info {
+        variables.extend([
+            ("device.mfr", info.company.clone()),
+            ("device.model", info.model.clone()),
+            ("ups.mfr", info.company.clone()),
+            ("ups.model", info.model.clone()),
+            ("ups.firmware", info.version.clone()),
+        ]);
+    }
+
+    if let Some(rating) = rating {
+        variables.extend([
+            ("output.voltage.nominal", format!("{:.1}", rating.voltage)),
+            ("output.current.nominal", rating.current.to_string()),
+            (
+                "battery.voltage.nominal",
+                format!("{:.1}", rating.battery_voltage),
+            ),
+            (
+                "output.frequency.nominal",
+                format!("{:.1}", rating.frequency),
+            ),
+        ]);
+    }
+
+    variables.sort_by_key(|(name, _)| *name);
+    variables
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::{NutError, NutReply, NutServer, NutSession, device_status};
+    use crate::{
+        config::{NutConfig, SimulatedConfig},
+        ups::{
+            AnyDeviceCreator, DeviceExecutor, DeviceRegistry, DeviceState, RegisteredDevice,
+            SimulatedDeviceCreator, command::FromDeviceResponse,
+        },
+    };
+
+    /// Creates a session for a server with a single simulated device
+    fn create_session() -> NutSession {
+        let creator =
+            AnyDeviceCreator::Simulated(SimulatedDeviceCreator::new(&SimulatedConfig::default()));
+        let executor = DeviceExecutor::start(creator);
+        let registry = DeviceRegistry::new(vec![RegisteredDevice {
+            id: "office".to_string(),
+            executor,
+            simulation: None,
+        }])
+        .unwrap();
+
+        let config = NutConfig {
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+
+        NutSession::new(Arc::new(NutServer::new(&config, Arc::new(registry))))
+    }
+
+    /// Helper to run a command line on a session
+    async fn run(session: &mut NutSession, line: &str) -> Result<NutReply, NutError> {
+        session.handle(&super::parse_line(line).unwrap()).await
+    }
+
+    /// Text reply helper
+    fn text(value: &str) -> Result<NutReply, NutError> {
+        Ok(NutReply::Text(value.to_string()))
+    }
+
+    /// Device state flags should map onto the NUT status flags
+    #[test]
+    fn test_device_status() {
+        let state = DeviceState::from_device_response(
+            "(000.0 240.0 240.0 020 50.0 22.0 30.0 11001001".into(),
+        )
+        .unwrap();
+
+        assert_eq!(device_status(&state, false), "OB LB");
+        assert_eq!(device_status(&state, true), "OB LB FSD");
+    }
+
+    /// Variables should be readable without logging in
+    #[tokio::test]
+    async fn test_read_variables() {
+        let mut session = create_session();
+
+        assert_eq!(
+            run(&mut session, "LIST UPS").await,
+            text("BEGIN LIST UPS\nUPS office \"OGuard UPS office\"\nEND LIST UPS\n")
+        );
+        assert_eq!(
+            run(&mut session, "GET VAR office ups.status").await,
+            text("VAR office ups.status \"OL\"\n")
+        );
+        assert_eq!(
+            run(&mut session, "GET VAR office battery.charge").await,
+            text("VAR office battery.charge \"100\"\n")
+        );
+        assert_eq!(
+            run(&mut session, "GET VAR office unknown.var").await,
+            Err(NutError::VarNotSupported)
+        );
+        assert_eq!(
+            run(&mut session, "GET VAR missing ups.status").await,
+            Err(NutError::UnknownUps)
+        );
+
+        let Ok(NutReply::Text(list)) = run(&mut session, "LIST VAR office").await else {
+            panic!("expected variable list");
+        };
+        assert!(list.starts_with("BEGIN LIST VAR office\n"));
+        assert!(list.contains("VAR office ups.model \"Simulated\"\n"));
+        assert!(list.ends_with("END LIST VAR office\n"));
+    }
+
+    /// Forced shutdown should require valid credentials and be reported in the status
+    #[tokio::test]
+    async fn test_login_forced_shutdown() {
+        let mut session = create_session();
+
+        assert_eq!(
+            run(&mut session, "LOGIN office").await,
+            Err(NutError::UsernameRequired)
+        );
+        assert_eq!(
+            run(&mut session, "FSD office").await,
+            Err(NutError::AccessDenied)
+        );
+
+        assert_eq!(run(&mut session, "USERNAME upsmon").await, text("OK\n"));
+        assert_eq!(run(&mut session, "PASSWORD secret").await, text("OK\n"));
+        assert_eq!(run(&mut session, "LOGIN office").await, text("OK\n"));
+        assert_eq!(
+            run(&mut session, "GET NUMLOGINS office").await,
+            text("NUMLOGINS office 1\n")
+        );
+        assert_eq!(
+            run(&mut session, "PRIMARY office").await,
+            text("OK PRIMARY-GRANTED\n")
+        );
+        assert_eq!(run(&mut session, "FSD office").await, text("OK FSD-SET\n"));
+        assert_eq!(
+            run(&mut session, "GET VAR office ups.status").await,
+            text("VAR office ups.status \"OL FSD\"\n")
+        );
+        assert_eq!(run(&mut session, "LOGOUT").await, Ok(NutReply::Logout));
+    }
+}
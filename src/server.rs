@@ -1,22 +1,28 @@
-use crate::config::Config;
+use crate::action::EventPipelineRunner;
+use crate::config::{Config, HistoryConfig};
 use crate::database;
 use crate::http::router;
+use crate::nut::server::NutServer;
 use crate::services::event_tracker::UPSEventTracker;
 use crate::services::history_tracker::UPSHistoryTracker;
 use crate::services::watcher::{UPSWatcher, UPSWatcherHandle};
-use crate::ups::DeviceExecutor;
-use crate::ups::device::HidDeviceCreator;
-use crate::{action::EventPipelineRunner, ups::DeviceExecutorHandle};
+use crate::ups::{
+    AnyDeviceCreator, DeviceExecutor, DeviceRegistry, RegisteredDevice, SharedDeviceRegistry,
+    command::ResponseCache,
+};
 use axum::Extension;
 use axum_session::{Key, SessionConfig, SessionLayer, SessionMode, SessionNullPool, SessionStore};
 use log::debug;
 use rust_i18n::t;
 use sea_orm::DatabaseConnection;
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tokio::sync::mpsc;
 
 /// Starts and runs the app server until the `shutdown_rx` receives a message
-pub async fn run_server(config: Config, shutdown_rx: mpsc::Receiver<()>) -> anyhow::Result<()> {
+pub async fn run_server(mut config: Config, shutdown_rx: mpsc::Receiver<()>) -> anyhow::Result<()> {
+    // Correct invalid values now that logging is available
+    config.validate();
+
     let config = Arc::new(config);
 
     // Set current locale
@@ -25,14 +31,43 @@ pub async fn run_server(config: Config, shutdown_rx: mpsc::Receiver<()>) -> anyh
     // Connect to the database
     let database = database::init().await;
 
-    // Start the executor
-    let executor = DeviceExecutor::start(HidDeviceCreator::new()?)?;
+    // Start an executor for each of the devices
+    let devices = config
+        .device_configs()
+        .map(|device_config| {
+            let device_creator = AnyDeviceCreator::from_config(device_config)?;
+            let simulation = device_creator.simulation();
+            let cache = ResponseCache::with_durations(
+                device_config
+                    .cache_ttl_ms
+                    .iter()
+                    .map(|(command, ttl_ms)| (command.into(), Duration::from_millis(*ttl_ms))),
+            );
+            let executor =
+                DeviceExecutor::start_with_options(device_creator, cache, device_config.protocol);
+            Ok(RegisteredDevice {
+                id: device_config.id.clone(),
+                executor,
+                simulation,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let registry: SharedDeviceRegistry = Arc::new(DeviceRegistry::new(devices)?);
+
+    // Executor for the primary device, used by routes that don't specify a device
+    let executor = registry.primary().executor.clone();
+    let simulation = registry.primary().simulation.clone();
 
-    // Start an event watcher
-    let watcher_handle = UPSWatcher::start(executor.clone());
+    // Start the event watchers
+    let watcher_handle = UPSWatcher::start_all(&registry, &config.watcher, &database);
 
     // Start background services
-    start_services(&database, &executor, &watcher_handle);
+    start_services(&database, &registry, &watcher_handle, &config.history);
+
+    // Start the NUT server
+    if config.nut.enabled {
+        NutServer::start(&config.nut, registry.clone());
+    }
 
     // Create in memory session store
     let session_store = SessionStore::<SessionNullPool>::new(
@@ -51,9 +86,16 @@ pub async fn run_server(config: Config, shutdown_rx: mpsc::Receiver<()>) -> anyh
         .layer(SessionLayer::new(session_store))
         .layer(Extension(database))
         .layer(Extension(executor))
+        .layer(Extension(registry))
         .layer(Extension(watcher_handle))
         .layer(Extension(config));
 
+    // Simulation steering is only available when the primary device is simulated
+    let app = match simulation {
+        Some(simulation) => app.layer(Extension(simulation)),
+        None => app,
+    };
+
     // CORS layer required for development access
     #[cfg(debug_assertions)]
     let app = app.layer(debug_cors_layer());
@@ -82,17 +124,26 @@ pub async fn run_server(config: Config, shutdown_rx: mpsc::Receiver<()>) -> anyh
 /// Starts background services that depend on the app resources
 fn start_services(
     database: &DatabaseConnection,
-    executor: &DeviceExecutorHandle,
+    registry: &SharedDeviceRegistry,
     watcher_handle: &UPSWatcherHandle,
+    history_config: &HistoryConfig,
 ) {
-    // Start long term watcher that logs state to database
-    UPSHistoryTracker::start(database.clone(), executor.clone());
+    // Start long term watchers that log state to database
+    let history_interval = Duration::from_secs(history_config.interval_secs);
+    for device in registry.iter() {
+        UPSHistoryTracker::start(
+            database.clone(),
+            device.id.clone(),
+            device.executor.clone(),
+            history_interval,
+        );
+    }
 
     // Start the event tracker
     UPSEventTracker::start(database.clone(), watcher_handle.clone());
 
     // Start the event pipeline runner
-    EventPipelineRunner::start(database.clone(), watcher_handle.clone(), executor.clone());
+    EventPipelineRunner::start(database.clone(), watcher_handle.clone(), registry.clone());
 }
 
 /// CORS Layer required in development mode where the web server is
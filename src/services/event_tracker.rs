@@ -7,7 +7,10 @@ use chrono::Utc;
 use log::error;
 use sea_orm::DatabaseConnection;
 
-use crate::{database::entities::events::EventModel, services::watcher::UPSWatcherHandle};
+use crate::{
+    database::entities::events::EventModel,
+    services::watcher::{DeviceEvent, UPSWatcherHandle},
+};
 
 pub struct UPSEventTracker {
     /// Database connection to store the data
@@ -23,9 +26,24 @@ impl UPSEventTracker {
     }
 
     pub async fn process(mut self) {
-        while let Some(event) = self.watcher_handle.next().await {
+        while let Some(DeviceEvent {
+            device_id,
+            event,
+            battery_test,
+            snapshot,
+        }) = self.watcher_handle.next().await
+        {
             let current_time = Utc::now();
-            if let Err(err) = EventModel::create(&self.db, event, current_time).await {
+            if let Err(err) = EventModel::create(
+                &self.db,
+                device_id,
+                event,
+                battery_test,
+                snapshot,
+                current_time,
+            )
+            .await
+            {
                 error!("failed to save event to database: {err}");
             }
         }
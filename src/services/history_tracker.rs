@@ -1,12 +1,15 @@
 //! # History Tracker
 //!
-//! Service that polls the UPS device at fixed intervals obtaining the
+//! Service that polls the UPS device at a configured interval obtaining the
 //! current device state and battery persisting them to the database for
 //! historical tracking
 
 use crate::{
     database::entities::{battery_history::BatteryHistoryModel, state_history::StateHistoryModel},
-    ups::{DeviceBattery, DeviceExecutorHandle, DeviceState, QueryDeviceBattery, QueryDeviceState},
+    ups::{
+        DeviceBattery, DeviceExecutorHandle, DeviceId, DeviceState, QueryDeviceBattery,
+        QueryDeviceState,
+    },
 };
 use chrono::Utc;
 use log::{debug, error};
@@ -17,15 +20,16 @@ use tokio::{
     time::{Instant, MissedTickBehavior, interval_at},
 };
 
-/// Interval between each device state poll
-const POLL_INTERVAL: Duration = Duration::from_secs(60);
-
 /// Tracks UPS history over time persisting it to the database
 pub struct UPSHistoryTracker {
     /// Database connection to store the data
     db: DatabaseConnection,
+    /// ID of the device being tracked
+    device_id: DeviceId,
     /// Executor to execute the requests
     executor: DeviceExecutorHandle,
+    /// Interval between each stored device state
+    poll_interval: Duration,
     /// Last state response
     last_device_state: Option<DeviceState>,
     /// Last battery state response
@@ -33,10 +37,17 @@ pub struct UPSHistoryTracker {
 }
 
 impl UPSHistoryTracker {
-    pub fn start(db: DatabaseConnection, executor: DeviceExecutorHandle) -> JoinHandle<()> {
+    pub fn start(
+        db: DatabaseConnection,
+        device_id: DeviceId,
+        executor: DeviceExecutorHandle,
+        poll_interval: Duration,
+    ) -> JoinHandle<()> {
         let tracker = Self {
             executor,
+            poll_interval,
             db,
+            device_id,
             last_device_state: None,
             last_battery_state: None,
         };
@@ -46,8 +57,8 @@ impl UPSHistoryTracker {
     /// Handle polling the device state at the expected interval
     /// and checking any changes
     pub async fn process(mut self) {
-        let start = Instant::now() + POLL_INTERVAL;
-        let mut interval = interval_at(start, POLL_INTERVAL);
+        let start = Instant::now() + self.poll_interval;
+        let mut interval = interval_at(start, self.poll_interval);
         interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
         while self.executor.is_open() {
@@ -81,8 +92,13 @@ impl UPSHistoryTracker {
 
         let current_time = Utc::now();
 
-        if let Err(err) =
-            StateHistoryModel::create(&self.db, device_state.clone(), current_time).await
+        if let Err(err) = StateHistoryModel::create(
+            &self.db,
+            self.device_id.clone(),
+            device_state.clone(),
+            current_time,
+        )
+        .await
         {
             error!("failed to store current device state: {err}");
         }
@@ -113,8 +129,13 @@ impl UPSHistoryTracker {
 
         let current_time = Utc::now();
 
-        if let Err(err) =
-            BatteryHistoryModel::create(&self.db, battery_state.clone(), current_time).await
+        if let Err(err) = BatteryHistoryModel::create(
+            &self.db,
+            self.device_id.clone(),
+            battery_state.clone(),
+            current_time,
+        )
+        .await
         {
             error!("failed to store current battery state: {err}");
         }
@@ -5,165 +5,1035 @@
 //!
 //! - Switch between battery self testing mode
 //! - Reaching low battery level and returning to normal battery level
-//! - Failure and restoration of AC power
+//! - Failure and restoration of AC power, debounced to ignore brief brown-outs
+//!   with an unstable line reported when the power keeps bouncing
+//! - Device faults and output overloads
+//! - Loss and restoration of the connection to the device
 //!
 //! It will emit any events that occur to anyone listening with a [UPSWatcherHandle]
+//!
+//! The last state is persisted to the database and restored on startup so conditions
+//! that were already active before a restart don't trigger their events again, a
+//! [UPSEvent::ServiceStarted] event carrying the initial state is emitted instead
+//!
+//! A watcher is started for each device in the [SharedDeviceRegistry], all the
+//! watchers share a single [EventBus] with events tagged by their [DeviceId].
+//! Subscribers that fall behind the bus replay the events they missed from the
+//! recently sent events instead of losing them. Replay is best-effort, only the last
+//! [EVENT_REPLAY_BUFFER] events are kept in memory and they don't survive a restart
 
 use crate::{
-    database::entities::events::UPSEvent,
+    config::{PowerConfig, WatcherConfig},
+    database::entities::{events::UPSEvent, watcher_state::WatcherStateModel},
     ups::{
-        commands::QueryDeviceState,
+        BatteryTestMode, DeviceId, SharedDeviceRegistry,
+        commands::{QueryDeviceBattery, QueryDeviceState},
         executor::DeviceExecutorHandle,
-        models::{DevicePowerState, DeviceState},
+        models::{DeviceBattery, DevicePowerState, DeviceSnapshot, DeviceState},
     },
 };
+use chrono::Utc;
+use futures::{Stream, stream};
 use log::{error, info, warn};
-use std::time::Duration;
-use tokio::{sync::broadcast, time::sleep};
-use tokio_stream::wrappers::BroadcastStream;
+use sea_orm::DatabaseConnection;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::broadcast::{self, error::RecvError},
+    time::{sleep, timeout},
+};
 
-/// Interval between each device state poll
-const POLL_INTERVAL: Duration = Duration::from_secs(3);
+/// Number of events to buffer in the channel for each device
+const EVENT_BUFFER_PER_DEVICE: usize = 16;
+
+/// Number of recently sent events kept for replaying to subscribers that fell behind,
+/// subscribers that fall further behind lose the older events
+const EVENT_REPLAY_BUFFER: usize = 256;
+
+/// Maximum time to wait for the device battery when creating an event snapshot
+const BATTERY_QUERY_TIMEOUT: Duration = Duration::from_secs(2);
 
 /// Watcher that polls a UPS executor at fixed intervals
 /// to handle changes in the state
 pub struct UPSWatcher {
+    /// ID of the device being watched
+    device_id: DeviceId,
     /// Handle to the executor to poll
     executor: DeviceExecutorHandle,
-    /// Channel for emitting events
-    tx: broadcast::Sender<UPSEvent>,
+    /// Bus for emitting events
+    bus: EventBus,
+    /// Database connection for persisting the last state
+    db: DatabaseConnection,
+    /// Whether the initial device state has been processed
+    started: bool,
     /// Last known device state
     last_device_state: Option<DeviceState>,
+    /// Kind of the battery test that is currently running
+    battery_test: Option<BatteryTestMode>,
+    /// Whether the executor was connected to the device on the last poll
+    last_connected: Option<bool>,
+    /// Whether the device output was overloaded on the last poll
+    last_overloaded: Option<bool>,
+    /// Last known device battery, used for event snapshots
+    /// when the battery could not be queried
+    last_battery: Option<DeviceBattery>,
+    /// Debounced AC power state
+    power: PowerTransitions,
+    /// When the device was last seen switching to AC power while not self testing
+    on_line_since: Option<Instant>,
+    /// Watcher configuration
+    config: WatcherConfig,
+}
+
+/// Event that occurred on a specific device
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceEvent {
+    /// ID of the device the event occurred on
+    pub device_id: DeviceId,
+    /// The event that occurred
+    pub event: UPSEvent,
+    /// Kind of battery test for battery test events, [None] for other
+    /// events or when the test was not requested through the API
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub battery_test: Option<BatteryTestMode>,
+    /// Snapshot of the device that triggered the event, [None]
+    /// for events about the connection to the device
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<DeviceSnapshot>,
+}
+
+/// Event tagged with its position in the [EventBus]
+#[derive(Debug, Clone)]
+struct SequencedEvent {
+    sequence: u64,
+    event: DeviceEvent,
+}
+
+/// Recently sent events kept for replaying
+#[derive(Default)]
+struct ReplayBuffer {
+    events: VecDeque<SequencedEvent>,
+    /// Sequence number for the next sent event
+    next_sequence: u64,
+}
+
+/// Channel shared by the watchers for emitting events, recently sent events
+/// are kept so subscribers that fall behind can replay the events they missed
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<SequencedEvent>,
+    replay: Arc<Mutex<ReplayBuffer>>,
+}
+
+impl EventBus {
+    /// Creates a new event bus buffering `capacity` events in the channel
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Self {
+            tx,
+            replay: Default::default(),
+        }
+    }
+
+    /// Sends an event to all the subscribers
+    pub fn send(&self, event: DeviceEvent) {
+        // Lock is held while sending so the channel order matches the sequence
+        let mut replay = self.replay.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let event = SequencedEvent {
+            sequence: replay.next_sequence,
+            event,
+        };
+        replay.next_sequence += 1;
+
+        if replay.events.len() == EVENT_REPLAY_BUFFER {
+            replay.events.pop_front();
+        }
+        replay.events.push_back(event.clone());
+
+        // Sending only fails when there are no subscribers
+        _ = self.tx.send(event);
+    }
+
+    /// Creates a handle that receives all events sent after subscribing
+    pub fn subscribe(&self) -> UPSWatcherHandle {
+        let replay = self.replay.lock().unwrap_or_else(PoisonError::into_inner);
+
+        UPSWatcherHandle {
+            rx: self.tx.subscribe(),
+            replay: self.replay.clone(),
+            next_sequence: replay.next_sequence,
+            missed: VecDeque::new(),
+        }
+    }
 }
 
 /// Handle to a [UPSWatcher] to receive messages/events
 pub struct UPSWatcherHandle {
-    pub(crate) rx: broadcast::Receiver<UPSEvent>,
+    rx: broadcast::Receiver<SequencedEvent>,
+    replay: Arc<Mutex<ReplayBuffer>>,
+    /// Sequence number of the next event to receive
+    next_sequence: u64,
+    /// Missed events waiting to be replayed
+    missed: VecDeque<SequencedEvent>,
 }
 
 impl Clone for UPSWatcherHandle {
     fn clone(&self) -> Self {
+        let replay = self.replay.lock().unwrap_or_else(PoisonError::into_inner);
+
         Self {
             rx: self.rx.resubscribe(),
+            replay: self.replay.clone(),
+            next_sequence: replay.next_sequence,
+            missed: VecDeque::new(),
         }
     }
 }
 
 impl UPSWatcherHandle {
-    /// Converts the underlying channel of the watcher handle into
-    /// a stream for reading events.
+    /// Converts the watcher handle into a stream for reading events.
     ///
     /// Used by the SSE API endpoint for sharing events with clients
-    pub fn into_stream(self) -> BroadcastStream<UPSEvent> {
-        BroadcastStream::new(self.rx)
+    pub fn into_stream(self) -> impl Stream<Item = DeviceEvent> {
+        stream::unfold(self, |mut handle| async move {
+            let event = handle.next().await?;
+            Some((event, handle))
+        })
+    }
+
+    /// Receive the next watcher message, events missed by falling
+    /// behind are replayed in order
+    pub async fn next(&mut self) -> Option<DeviceEvent> {
+        loop {
+            let event = match self.missed.pop_front() {
+                Some(event) => event,
+                None => match self.rx.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(count)) => {
+                        warn!("event subscriber fell behind by {count} events, replaying");
+                        self.replay_missed();
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+            };
+
+            // Event was already received through a replay
+            if event.sequence < self.next_sequence {
+                continue;
+            }
+
+            self.next_sequence = event.sequence + 1;
+            return Some(event.event);
+        }
     }
 
-    /// Receive the next watcher message
-    pub async fn next(&mut self) -> Option<UPSEvent> {
-        self.rx.recv().await.ok()
+    /// Queues the events that were missed from the replay buffer
+    fn replay_missed(&mut self) {
+        let replay = self.replay.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if let Some(oldest) = replay.events.front()
+            && oldest.sequence > self.next_sequence
+        {
+            error!(
+                "{} events were lost before they could be replayed",
+                oldest.sequence - self.next_sequence
+            );
+        }
+
+        self.missed.extend(
+            replay
+                .events
+                .iter()
+                .filter(|event| event.sequence >= self.next_sequence)
+                .cloned(),
+        );
     }
 }
 
 impl UPSWatcher {
+    /// Starts a UPS watcher for each of the devices in the registry, events
+    /// from all the devices are received through the returned handle
+    pub fn start_all(
+        registry: &SharedDeviceRegistry,
+        config: &WatcherConfig,
+        db: &DatabaseConnection,
+    ) -> UPSWatcherHandle {
+        let capacity = EVENT_BUFFER_PER_DEVICE * registry.iter().count();
+        let bus = EventBus::new(capacity);
+        let handle = bus.subscribe();
+
+        for device in registry.iter() {
+            Self::start(
+                device.id.clone(),
+                device.executor.clone(),
+                bus.clone(),
+                config.clone(),
+                db.clone(),
+            );
+        }
+
+        handle
+    }
+
     /// Starts a UPS watcher that will watch the provided executor handle
-    pub fn start(executor: DeviceExecutorHandle) -> UPSWatcherHandle {
-        let (tx, rx) = broadcast::channel(4);
-        let watcher = Self {
-            executor,
-            last_device_state: None,
-            tx,
-        };
+    /// emitting events through the provided bus
+    pub fn start(
+        device_id: DeviceId,
+        executor: DeviceExecutorHandle,
+        bus: EventBus,
+        config: WatcherConfig,
+        db: DatabaseConnection,
+    ) {
+        let watcher = Self::new(device_id, executor, bus, config, db);
         tokio::spawn(watcher.process());
+    }
 
-        UPSWatcherHandle { rx }
+    /// Creates a UPS watcher for the provided executor handle
+    fn new(
+        device_id: DeviceId,
+        executor: DeviceExecutorHandle,
+        bus: EventBus,
+        config: WatcherConfig,
+        db: DatabaseConnection,
+    ) -> Self {
+        Self {
+            device_id,
+            executor,
+            last_device_state: None,
+            battery_test: None,
+            last_connected: None,
+            last_overloaded: None,
+            last_battery: None,
+            power: PowerTransitions::new(config.power.clone()),
+            on_line_since: None,
+            config,
+            bus,
+            db,
+            started: false,
+        }
     }
 
     /// Pushes a new event to any of the watchers
     pub fn push_event(&mut self, event: UPSEvent) {
-        _ = self.tx.send(event);
+        self.push_snapshot_event(event, None, None);
+    }
+
+    /// Pushes a new event along with the device snapshot that
+    /// triggered it to any of the watchers
+    pub fn push_snapshot_event(
+        &mut self,
+        event: UPSEvent,
+        battery_test: Option<BatteryTestMode>,
+        snapshot: Option<DeviceSnapshot>,
+    ) {
+        self.bus.send(DeviceEvent {
+            device_id: self.device_id.clone(),
+            event,
+            battery_test,
+            snapshot,
+        });
     }
 
     /// Handle polling the device state at the expected interval
     /// and checking any changes
     pub async fn process(mut self) {
-        while self.tx.receiver_count() > 0 && self.executor.is_open() {
-            self.process_device_state().await;
+        while self.executor.is_open() {
+            // Connection state is unknown until the first connection attempt finishes,
+            // device state can't be queried while disconnected
+            if !self.executor.is_connecting() && self.process_connection_state() {
+                self.process_device_state().await;
+            }
 
-            sleep(POLL_INTERVAL).await;
+            sleep(self.poll_interval()).await;
         }
     }
 
+    /// Determines the interval before the next poll based on the last device state
+    fn poll_interval(&self) -> Duration {
+        let on_line_for = self.on_line_since.map(|since| since.elapsed());
+
+        self.config
+            .polling
+            .interval(self.last_device_state.as_ref(), on_line_for)
+    }
+
+    /// Checks whether the executor connection to the device has changed,
+    /// emits events for lost and restored connections. Provides whether the
+    /// executor is currently connected
+    pub fn process_connection_state(&mut self) -> bool {
+        let connected = self.executor.is_connected();
+
+        match (self.last_connected, connected) {
+            // Should trigger disconnect event if there is a transition or none previous state
+            (Some(true) | None, false) => {
+                warn!("Device is disconnected ({})", self.device_id);
+
+                self.push_event(UPSEvent::DeviceDisconnected);
+            }
+            (Some(false), true) => {
+                info!("Device has reconnected ({})", self.device_id);
+
+                self.push_event(UPSEvent::DeviceReconnected);
+            }
+            _ => {}
+        }
+
+        self.last_connected = Some(connected);
+        connected
+    }
+
     /// Requests the current device state from the executor and checks
     /// for any state changes, emits events for changed states
     pub async fn process_device_state(&mut self) {
         let device_state = match self.executor.send(QueryDeviceState).await {
             Ok(value) => value,
             Err(err) => {
-                error!("Error while requesting UPS device state: {err:?}");
+                error!(
+                    "Error while requesting UPS device state ({}): {err:?}",
+                    self.device_id
+                );
                 return;
             }
         };
 
+        // Restore the state from before the service started
+        let initial_state = !self.started;
+        if initial_state {
+            self.started = true;
+            self.restore_state(&device_state).await;
+
+            info!("Device watcher started ({})", self.device_id);
+
+            let snapshot = self.create_snapshot(&device_state).await;
+            self.push_snapshot_event(UPSEvent::ServiceStarted, None, Some(snapshot));
+        }
+
         // Obtain the previous states if available
-        let (last_battery_self_test, last_battery_low, last_device_power_state) = self
-            .last_device_state
-            .as_ref()
-            .map(|value| {
-                (
-                    Some(value.battery_self_test),
-                    Some(value.battery_low),
-                    Some(value.device_power_state),
-                )
-            })
-            .unwrap_or_default();
+        let (last_battery_self_test, last_battery_low, last_fault_mode, last_shutdown_active) =
+            self.last_device_state
+                .as_ref()
+                .map(|value| {
+                    (
+                        Some(value.battery_self_test),
+                        Some(value.battery_low),
+                        Some(value.fault_mode),
+                        Some(value.shutdown_active),
+                    )
+                })
+                .unwrap_or_default();
+
+        // Events triggered by the state along with the battery test kind
+        let mut events: Vec<(UPSEvent, Option<BatteryTestMode>)> = Vec::new();
 
         // Battery self tests
         match (last_battery_self_test, device_state.battery_self_test) {
             // Should trigger enter event if there is a transition or none previous state
             (Some(false) | None, true) => {
-                info!("Device has started self test");
+                // Take the test kind that was requested through the executor
+                self.battery_test = self
+                    .executor
+                    .control_state()
+                    .lock()
+                    .ok()
+                    .and_then(|mut control_state| control_state.battery_test.take());
+
+                info!("Device has started self test ({:?})", self.battery_test);
 
-                self.push_event(UPSEvent::BatteryTestStart);
+                events.push((UPSEvent::BatteryTestStart, self.battery_test));
             }
             (Some(true), false) => {
                 info!("Device has finished self test");
 
-                self.push_event(UPSEvent::BatteryTestEnd);
+                let battery_test = self.battery_test.take();
+                events.push((UPSEvent::BatteryTestEnd, battery_test));
             }
             _ => {}
         }
 
+        // UPS shutdown has finished or was cancelled outside the API
+        if last_shutdown_active == Some(true)
+            && !device_state.shutdown_active
+            && let Ok(control_state) = &mut self.executor.control_state().lock()
+        {
+            info!("Device is no longer shutting down");
+            control_state.ups_shutdown = None;
+        }
+
         // Low battery
         match (last_battery_low, device_state.battery_low) {
             // Should trigger enter event if there is a transition or none previous state
             (Some(false) | None, true) => {
                 info!("Device is running low on battery");
 
-                self.push_event(UPSEvent::LowBatteryModeStart);
+                events.push((UPSEvent::LowBatteryModeStart, None));
             }
             (Some(true), false) => {
                 info!("Device is no longer low on battery");
 
-                self.push_event(UPSEvent::LowBatteryModeEnd);
+                events.push((UPSEvent::LowBatteryModeEnd, None));
             }
             _ => {}
         }
 
         // Power transitions
-        match (last_device_power_state, device_state.device_power_state) {
-            (Some(DevicePowerState::Utility) | None, DevicePowerState::Battery) => {
-                warn!("AC FAILURE");
+        let power_events = self
+            .power
+            .update(device_state.device_power_state, Instant::now());
 
-                self.push_event(UPSEvent::ACFailure);
+        for event in power_events {
+            match event {
+                UPSEvent::ACFailure => warn!("AC FAILURE"),
+                UPSEvent::ACRecovery => info!("AC RECOVERY"),
+                UPSEvent::PowerUnstable => warn!("AC power is unstable"),
+                _ => {}
             }
-            (Some(DevicePowerState::Battery), DevicePowerState::Utility) => {
-                info!("AC RECOVERY");
 
-                self.push_event(UPSEvent::ACRecovery);
+            events.push((event, None));
+        }
+
+        // Device faults
+        match (last_fault_mode, device_state.fault_mode) {
+            // Should trigger enter event if there is a transition or none previous state
+            (Some(false) | None, true) => {
+                warn!("Device has encountered a fault");
+
+                events.push((UPSEvent::UPSFault, None));
+            }
+            (Some(true), false) => {
+                info!("Device fault has cleared");
+
+                events.push((UPSEvent::UPSFaultCleared, None));
             }
             _ => {}
-        };
+        }
+
+        // Output overload, thresholds have hysteresis to prevent flapping
+        let overloaded = self.config.overload.is_overloaded(
+            self.last_overloaded.unwrap_or_default(),
+            device_state.output_load_percent,
+        );
+
+        match (self.last_overloaded, overloaded) {
+            // Should trigger enter event if there is a transition or none previous state
+            (Some(false) | None, true) => {
+                warn!(
+                    "Device output is overloaded ({}%)",
+                    device_state.output_load_percent
+                );
+
+                events.push((UPSEvent::OverloadStart, None));
+            }
+            (Some(true), false) => {
+                info!(
+                    "Device output is no longer overloaded ({}%)",
+                    device_state.output_load_percent
+                );
+
+                events.push((UPSEvent::OverloadEnd, None));
+            }
+            _ => {}
+        }
+
+        self.last_overloaded = Some(overloaded);
+
+        if device_state.device_power_state == DevicePowerState::Utility
+            && !device_state.battery_self_test
+        {
+            self.on_line_since.get_or_insert_with(Instant::now);
+        } else {
+            self.on_line_since = None;
+        }
+
+        let changed = !events.is_empty();
+        if changed {
+            let snapshot = self.create_snapshot(&device_state).await;
+            for (event, battery_test) in events {
+                self.push_snapshot_event(event, battery_test, Some(snapshot.clone()));
+            }
+        }
+
+        // State only needs to be persisted when it has triggered events
+        if changed || initial_state {
+            self.persist_state(&device_state).await;
+        }
 
         self.last_device_state = Some(device_state);
     }
+
+    /// Restores the last state persisted before the service started, when there
+    /// is no persisted state the current state is used so conditions that are
+    /// already active don't trigger events
+    async fn restore_state(&mut self, device_state: &DeviceState) {
+        let saved = match WatcherStateModel::get(&self.db, &self.device_id).await {
+            Ok(value) => value,
+            Err(err) => {
+                error!(
+                    "Failed to load persisted watcher state ({}): {err}",
+                    self.device_id
+                );
+                None
+            }
+        };
+
+        let (state, power_state, overloaded) = match saved {
+            Some(saved) => {
+                info!(
+                    "Restored watcher state from {} ({})",
+                    saved.updated_at, self.device_id
+                );
+                // Rows saved before the power state was stored only have the device state
+                let power_state = saved.power_state.unwrap_or(saved.state.device_power_state);
+                (saved.state, power_state, saved.overloaded)
+            }
+            None => {
+                let overloaded = self
+                    .config
+                    .overload
+                    .is_overloaded(false, device_state.output_load_percent);
+                let power_state = device_state.device_power_state;
+                (device_state.clone(), power_state, overloaded)
+            }
+        };
+
+        // Power state events were emitted for may differ from the device state when
+        // the service stopped during a debounce or while the line was unstable
+        self.power.restore(power_state);
+        self.last_overloaded = Some(overloaded);
+        self.last_device_state = Some(state);
+    }
+
+    /// Persists the state so it can be restored when the service starts, the
+    /// debounced power and overload states are stored rather than the raw
+    /// device state so transitions that haven't emitted events aren't lost
+    async fn persist_state(&self, device_state: &DeviceState) {
+        if let Err(err) = WatcherStateModel::save(
+            &self.db,
+            self.device_id.clone(),
+            device_state.clone(),
+            self.power.stable,
+            self.last_overloaded.unwrap_or_default(),
+            Utc::now(),
+        )
+        .await
+        {
+            error!(
+                "Failed to persist watcher state ({}): {err}",
+                self.device_id
+            );
+        }
+    }
+
+    /// Creates a snapshot of the device from the provided state, the battery
+    /// is only queried when a snapshot is needed to avoid polling it constantly.
+    /// The query is given a short time to respond so events aren't held up by a
+    /// slow device, the last known battery is used when the query fails
+    async fn create_snapshot(&mut self, device_state: &DeviceState) -> DeviceSnapshot {
+        match timeout(
+            BATTERY_QUERY_TIMEOUT,
+            self.executor.send(QueryDeviceBattery),
+        )
+        .await
+        {
+            Ok(Ok(battery)) => self.last_battery = Some(battery),
+            Ok(Err(err)) => {
+                warn!(
+                    "Failed to request UPS battery for event snapshot ({}): {err:?}",
+                    self.device_id
+                );
+            }
+            Err(_) => {
+                warn!(
+                    "Timed out requesting UPS battery for event snapshot ({})",
+                    self.device_id
+                );
+            }
+        }
+
+        DeviceSnapshot {
+            state: device_state.clone(),
+            battery: self.last_battery.clone(),
+        }
+    }
+}
+
+/// Tracks the AC power state of a device, transitions are only accepted once
+/// the new state has held for the configured debounce. Transitions are held off
+/// while the line is unstable (changing state repeatedly)
+struct PowerTransitions {
+    config: PowerConfig,
+    /// Power state that events were last emitted for
+    stable: Option<DevicePowerState>,
+    /// Power state that is waiting for its debounce
+    pending: Option<PendingPowerState>,
+    /// Power state from the last poll
+    last_state: Option<DevicePowerState>,
+    /// Times of the recent power state changes
+    changes: VecDeque<Instant>,
+    /// Whether the line is currently unstable
+    unstable: bool,
+}
+
+/// Power state that hasn't been accepted yet
+struct PendingPowerState {
+    state: DevicePowerState,
+    /// When the state was first seen
+    since: Instant,
+    /// Number of consecutive polls the state was seen for
+    polls: u32,
+}
+
+impl PowerTransitions {
+    fn new(config: PowerConfig) -> Self {
+        Self {
+            config,
+            stable: None,
+            pending: None,
+            last_state: None,
+            changes: VecDeque::new(),
+            unstable: false,
+        }
+    }
+
+    /// Restores the power state that events were last emitted for, the
+    /// next poll is not counted as a change so a restart can't make the
+    /// line unstable
+    fn restore(&mut self, state: DevicePowerState) {
+        self.stable = Some(state);
+    }
+
+    /// Updates the tracked state with the power state from the latest
+    /// poll, provides the events that should be emitted
+    fn update(&mut self, state: DevicePowerState, now: Instant) -> Vec<UPSEvent> {
+        let mut events = Vec::new();
+
+        if self
+            .last_state
+            .is_some_and(|last_state| last_state != state)
+        {
+            self.changes.push_back(now);
+        }
+        self.last_state = Some(state);
+
+        if self.update_unstable(now) {
+            events.push(UPSEvent::PowerUnstable);
+        }
+
+        if self.stable == Some(state) {
+            self.pending = None;
+            return events;
+        }
+
+        let pending = match &mut self.pending {
+            Some(pending) if pending.state == state => {
+                pending.polls += 1;
+                pending
+            }
+            pending => pending.insert(PendingPowerState {
+                state,
+                since: now,
+                polls: 1,
+            }),
+        };
+
+        let debounce = match state {
+            DevicePowerState::Battery => self.config.ac_failure,
+            DevicePowerState::Utility => self.config.ac_recovery,
+        };
+
+        let held = pending.polls >= debounce.polls
+            && now.duration_since(pending.since) >= Duration::from_secs(debounce.seconds);
+
+        // Transitions are held off until the line settles
+        if !held || self.unstable {
+            return events;
+        }
+
+        let last_stable = self.stable.replace(state);
+        self.pending = None;
+
+        match (last_stable, state) {
+            (Some(DevicePowerState::Utility) | None, DevicePowerState::Battery) => {
+                events.push(UPSEvent::ACFailure)
+            }
+            (Some(DevicePowerState::Battery), DevicePowerState::Utility) => {
+                events.push(UPSEvent::ACRecovery)
+            }
+            _ => {}
+        }
+
+        events
+    }
+
+    /// Updates whether the line is unstable based on the number of recent
+    /// state changes, provides whether the line has become unstable
+    fn update_unstable(&mut self, now: Instant) -> bool {
+        let flap = self.config.flap;
+        if !flap.enabled {
+            return false;
+        }
+
+        let window = Duration::from_secs(flap.window_secs);
+        while self
+            .changes
+            .front()
+            .is_some_and(|changed| now.duration_since(*changed) > window)
+        {
+            self.changes.pop_front();
+        }
+
+        if self.unstable {
+            // Line has settled once it hasn't changed for the whole window
+            if self.changes.is_empty() {
+                info!("AC power has stabilized");
+                self.unstable = false;
+            }
+
+            return false;
+        }
+
+        self.unstable = self.changes.len() >= flap.max_transitions;
+        self.unstable
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use chrono::Utc;
+    use sea_orm::DatabaseConnection;
+    use tokio::time::timeout;
+
+    use super::{DeviceEvent, EventBus, PowerTransitions, UPSWatcher, UPSWatcherHandle};
+    use crate::{
+        config::{DebounceConfig, FlapConfig, PowerConfig, SimulatedConfig, WatcherConfig},
+        database::{
+            connect_database,
+            entities::{events::UPSEvent, watcher_state::WatcherStateModel},
+        },
+        ups::{
+            DeviceExecutor, QueryDeviceState,
+            command::ResponseCache,
+            device::{AnyDevice, AnyDeviceCreator, SharedSimulation, SimulatedDeviceCreator},
+            models::DevicePowerState::{Battery, Utility},
+            registry::DEFAULT_DEVICE_ID,
+        },
+    };
+
+    /// AC failure should only be emitted once battery power has held for the debounce
+    #[test]
+    fn test_power_debounce() {
+        let mut power = PowerTransitions::new(PowerConfig {
+            ac_failure: DebounceConfig {
+                polls: 3,
+                seconds: 0,
+            },
+            flap: FlapConfig {
+                enabled: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        assert!(power.update(Utility, now).is_empty());
+        assert!(power.update(Battery, now).is_empty());
+        assert!(power.update(Utility, now).is_empty());
+        assert!(power.update(Battery, now).is_empty());
+        assert!(power.update(Battery, now).is_empty());
+        assert_eq!(power.update(Battery, now), vec![UPSEvent::ACFailure]);
+        assert_eq!(power.update(Utility, now), vec![UPSEvent::ACRecovery]);
+    }
+
+    /// A bouncing line should emit a single unstable event and hold off
+    /// power events until it has settled
+    #[test]
+    fn test_power_flapping() {
+        let mut power = PowerTransitions::new(PowerConfig::default());
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(power.update(Utility, at(0)).is_empty());
+        assert_eq!(power.update(Battery, at(1)), vec![UPSEvent::ACFailure]);
+        assert_eq!(power.update(Utility, at(2)), vec![UPSEvent::ACRecovery]);
+        assert_eq!(power.update(Battery, at(3)), vec![UPSEvent::ACFailure]);
+        assert_eq!(power.update(Utility, at(4)), vec![UPSEvent::PowerUnstable]);
+        assert!(power.update(Battery, at(5)).is_empty());
+        assert!(power.update(Utility, at(6)).is_empty());
+        assert!(power.update(Utility, at(30)).is_empty());
+
+        // Line has settled on AC power
+        assert_eq!(power.update(Utility, at(67)), vec![UPSEvent::ACRecovery]);
+    }
+
+    /// Restarting while a transition is being debounced should emit the event
+    /// once the restored transition has held for its debounce
+    #[test]
+    fn test_power_restart_debounce() {
+        let config = PowerConfig {
+            ac_failure: DebounceConfig {
+                polls: 3,
+                seconds: 0,
+            },
+            ..Default::default()
+        };
+        let now = Instant::now();
+
+        let mut power = PowerTransitions::new(config.clone());
+        assert!(power.update(Utility, now).is_empty());
+        assert!(power.update(Battery, now).is_empty());
+        assert!(power.update(Battery, now).is_empty());
+
+        // Service restarts before the debounce has held
+        let mut power = PowerTransitions::new(config);
+        power.restore(Utility);
+
+        assert!(power.update(Battery, now).is_empty());
+        assert!(power.update(Battery, now).is_empty());
+        assert_eq!(power.update(Battery, now), vec![UPSEvent::ACFailure]);
+    }
+
+    /// Restarting while the line is unstable should emit the event for
+    /// the state the line settled on
+    #[test]
+    fn test_power_restart_flapping() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let mut power = PowerTransitions::new(PowerConfig::default());
+        assert!(power.update(Utility, at(0)).is_empty());
+        assert_eq!(power.update(Battery, at(1)), vec![UPSEvent::ACFailure]);
+        assert_eq!(power.update(Utility, at(2)), vec![UPSEvent::ACRecovery]);
+        assert_eq!(power.update(Battery, at(3)), vec![UPSEvent::ACFailure]);
+        assert_eq!(power.update(Utility, at(4)), vec![UPSEvent::PowerUnstable]);
+        assert!(power.update(Battery, at(5)).is_empty());
+        assert!(power.update(Utility, at(6)).is_empty());
+        let stable = power.stable.expect("stable power state");
+
+        // Service restarts while the line is unstable and has settled on AC power
+        let mut power = PowerTransitions::new(PowerConfig::default());
+        power.restore(stable);
+
+        assert_eq!(power.update(Utility, at(7)), vec![UPSEvent::ACRecovery]);
+        assert!(power.update(Utility, at(8)).is_empty());
+    }
+
+    /// Creates a watcher for a simulated device along with the
+    /// simulation and a handle to receive the watcher events
+    fn simulated_watcher(
+        db: DatabaseConnection,
+    ) -> (UPSWatcher, SharedSimulation, UPSWatcherHandle) {
+        let creator = SimulatedDeviceCreator::new(&SimulatedConfig::default());
+        let simulation = creator.simulation().clone();
+
+        // Responses are not cached so each poll reads the simulation
+        let cache = ResponseCache::with_durations(
+            ["QS", "QI"].map(|command| (command.into(), Duration::ZERO)),
+        );
+        let executor = DeviceExecutor::<AnyDevice>::start_with_options(
+            AnyDeviceCreator::Simulated(creator),
+            cache,
+            None,
+        );
+
+        let bus = EventBus::new(16);
+        let events = bus.subscribe();
+        let watcher = UPSWatcher::new(
+            DEFAULT_DEVICE_ID.to_string(),
+            executor,
+            bus,
+            WatcherConfig::default(),
+            db,
+        );
+
+        (watcher, simulation, events)
+    }
+
+    /// State saved before the power state was stored should restore the
+    /// power state from the device state instead of emitting its events again
+    #[tokio::test]
+    async fn test_restore_without_power_state() {
+        let db = connect_database("sqlite::memory:").await;
+        let (mut watcher, simulation, mut events) = simulated_watcher(db.clone());
+
+        simulation.lock().unwrap().set_ac_power(false);
+        let state = watcher.executor.send(QueryDeviceState).await.unwrap();
+        WatcherStateModel::save(
+            &db,
+            DEFAULT_DEVICE_ID.to_string(),
+            state,
+            None,
+            false,
+            Utc::now(),
+        )
+        .await
+        .unwrap();
+
+        watcher.process_device_state().await;
+
+        let event = events.next().await.unwrap();
+        assert_eq!(event.event, UPSEvent::ServiceStarted);
+
+        let next = timeout(Duration::from_millis(100), events.next()).await;
+        assert!(next.is_err(), "AC failure should not be emitted again");
+    }
+
+    /// Event snapshots should carry the battery from the poll that triggered the event
+    #[tokio::test]
+    async fn test_snapshot_battery() {
+        let db = connect_database("sqlite::memory:").await;
+        let (mut watcher, simulation, mut events) = simulated_watcher(db);
+
+        simulation.lock().unwrap().set_capacity(80);
+        watcher.process_device_state().await;
+
+        let event = events.next().await.unwrap();
+        assert_eq!(event.event, UPSEvent::ServiceStarted);
+        assert_eq!(event.snapshot.unwrap().battery.unwrap().capacity, 80);
+
+        {
+            let mut simulation = simulation.lock().unwrap();
+            simulation.set_ac_power(false);
+            simulation.set_capacity(55);
+        }
+        watcher.process_device_state().await;
+
+        let event = events.next().await.unwrap();
+        assert_eq!(event.event, UPSEvent::ACFailure);
+        assert_eq!(event.snapshot.unwrap().battery.unwrap().capacity, 55);
+    }
+
+    /// Subscribers that fall behind should replay the missed events in order
+    #[tokio::test]
+    async fn test_event_bus_lagged() {
+        let bus = EventBus::new(2);
+        let mut handle = bus.subscribe();
+
+        let events = [
+            UPSEvent::ACFailure,
+            UPSEvent::LowBatteryModeStart,
+            UPSEvent::LowBatteryModeEnd,
+            UPSEvent::ACRecovery,
+            UPSEvent::OverloadStart,
+        ];
+
+        for event in events {
+            bus.send(DeviceEvent {
+                device_id: DEFAULT_DEVICE_ID.to_string(),
+                event,
+                battery_test: None,
+                snapshot: None,
+            });
+        }
+
+        for event in events {
+            assert_eq!(handle.next().await.map(|value| value.event), Some(event));
+        }
+    }
 }
@@ -1,10 +1,17 @@
 use std::{
     any::Any,
     collections::HashMap,
+    str::{FromStr, Split},
     time::{Duration, Instant},
 };
 
 use compact_str::CompactString;
+use ordered_float::OrderedFloat;
+use serde::Serialize;
+use strum::Display;
+use thiserror::Error;
+
+use super::{control::ControlState, protocol::Protocol};
 
 /// Trait implemented by structures that can be used
 /// as device commands
@@ -12,24 +19,191 @@ pub trait IntoDeviceCommand: Send + 'static {
     /// Response type to decode
     type Response: FromDeviceResponse;
 
+    /// Stable name of the command, diagnostics are grouped by the name
+    /// so commands with arguments (i.e raw commands) share a single entry
+    fn name(&self) -> &'static str;
+
     /// Gets the command string to send to the device
     fn get_command(&self) -> CompactString;
 
+    /// Gets the command string to send to a device speaking the provided
+    /// protocol, commands that differ between protocols override this
+    fn get_protocol_command(&self, _protocol: Protocol) -> CompactString {
+        self.get_command()
+    }
+
+    /// Parses the device response for a device speaking the provided protocol
+    fn parse_response(
+        &self,
+        _protocol: Protocol,
+        value: CompactString,
+    ) -> anyhow::Result<Self::Response> {
+        Self::Response::from_device_response(value)
+    }
+
     /// Unique cache key to use if the response can be cached
     fn cache_key(&self) -> Option<u64> {
         None
     }
 
+    /// Duration a cached response for this command remains valid
+    fn cache_duration(&self) -> Duration {
+        ResponseCache::CACHE_TIME
+    }
+
     /// Invalidates any cache keys this command will have an affect on.
     /// Used by queries that manipulate the device in order to discard
     /// cached device states
     fn invalidate_cache(&self, _cache: &mut ResponseCache) {}
+
+    /// Updates the device control state after the command was executed
+    /// successfully. Used by control commands to record what was requested
+    fn update_control_state(&self, _state: &mut ControlState) {}
+
+    /// Priority of the command while waiting to be executed, control
+    /// commands should be executed ahead of queries
+    fn priority(&self) -> CommandPriority {
+        CommandPriority::Query
+    }
+
+    /// Maximum time the command can wait to be executed before it
+    /// is dropped with a timeout error
+    fn deadline(&self) -> Duration {
+        match self.priority() {
+            CommandPriority::Query => QUERY_DEADLINE,
+            CommandPriority::Control => CONTROL_DEADLINE,
+        }
+    }
+}
+
+/// Default time a query can wait to be executed
+pub const QUERY_DEADLINE: Duration = Duration::from_secs(10);
+
+/// Default time a control command can wait to be executed
+pub const CONTROL_DEADLINE: Duration = Duration::from_secs(30);
+
+/// Priority for executing a command, waiting commands with a higher
+/// priority are executed first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CommandPriority {
+    /// Queries for the current device state
+    Query,
+    /// Commands that control the device
+    Control,
 }
 
 pub trait FromDeviceResponse: Send + Clone + 'static {
     fn from_device_response(value: CompactString) -> anyhow::Result<Self>;
 }
 
+/// Error from parsing a device response, keeps the raw response along
+/// with the field that failed to parse
+#[derive(Debug, Clone, Error)]
+#[error("{kind} {field} (field {index}) in device response {response:?}")]
+pub struct ResponseParseError {
+    /// The raw response from the device
+    pub response: CompactString,
+    /// Name of the field that failed to parse
+    pub field: &'static str,
+    /// Index of the field within the response
+    pub index: usize,
+    /// Why the field failed to parse
+    pub kind: ParseErrorKind,
+}
+
+impl ResponseParseError {
+    pub fn new(
+        response: &CompactString,
+        field: &'static str,
+        index: usize,
+        kind: ParseErrorKind,
+    ) -> Self {
+        Self {
+            response: response.clone(),
+            field,
+            index,
+            kind,
+        }
+    }
+}
+
+/// Reason a response field failed to parse
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseErrorKind {
+    /// Field was not present in the response
+    Missing,
+    /// Field was present but its value was invalid
+    Invalid,
+}
+
+/// Space separated fields of a device response, tracks the index of
+/// each field to create [ResponseParseError]s for the failed field
+pub struct ResponseFields<'a> {
+    /// The raw response
+    response: &'a CompactString,
+    /// Remaining fields in the response
+    parts: Split<'a, char>,
+    /// Index of the next field
+    index: usize,
+}
+
+impl<'a> ResponseFields<'a> {
+    /// Creates the fields for a response that should start with `prefix`
+    pub fn new(response: &'a CompactString, prefix: char) -> Result<Self, ResponseParseError> {
+        let value = response.strip_prefix(prefix).ok_or_else(|| {
+            ResponseParseError::new(response, "prefix", 0, ParseErrorKind::Missing)
+        })?;
+
+        Ok(Self {
+            response,
+            parts: value.split(' '),
+            index: 0,
+        })
+    }
+
+    /// Takes the next field without parsing it
+    pub fn next_str(&mut self, field: &'static str) -> Result<&'a str, ResponseParseError> {
+        let value = self
+            .parts
+            .next()
+            .ok_or_else(|| self.error(field, self.index, ParseErrorKind::Missing))?;
+        self.index += 1;
+        Ok(value)
+    }
+
+    /// Takes and parses the next field
+    pub fn next<T: FromStr>(&mut self, field: &'static str) -> Result<T, ResponseParseError> {
+        self.next_str(field)?
+            .parse()
+            .map_err(|_| self.invalid(field))
+    }
+
+    /// Takes and parses the next field for a value that the device may report
+    /// as unavailable using dashes (i.e `--.-`), unavailable values are [None]
+    pub fn next_optional(
+        &mut self,
+        field: &'static str,
+    ) -> Result<Option<OrderedFloat<f64>>, ResponseParseError> {
+        let value = self.next_str(field)?;
+        if value.chars().all(|char| matches!(char, '-' | '.')) {
+            return Ok(None);
+        }
+
+        value.parse().map(Some).map_err(|_| self.invalid(field))
+    }
+
+    /// Creates an error for the last field that was taken being invalid
+    pub fn invalid(&self, field: &'static str) -> ResponseParseError {
+        self.error(field, self.index.saturating_sub(1), ParseErrorKind::Invalid)
+    }
+
+    /// Creates an error for the field at the provided index
+    fn error(&self, field: &'static str, index: usize, kind: ParseErrorKind) -> ResponseParseError {
+        ResponseParseError::new(self.response, field, index, kind)
+    }
+}
+
 impl FromDeviceResponse for () {
     #[inline]
     fn from_device_response(_value: CompactString) -> anyhow::Result<Self> {
@@ -37,10 +211,20 @@ impl FromDeviceResponse for () {
     }
 }
 
+impl FromDeviceResponse for CompactString {
+    #[inline]
+    fn from_device_response(value: CompactString) -> anyhow::Result<Self> {
+        Ok(value)
+    }
+}
+
 /// Cache for storing device command responses
 #[derive(Default)]
 pub struct ResponseCache {
     cache: HashMap<u64, CachedValue>,
+    /// Durations to cache responses for keyed by command, overrides the
+    /// [IntoDeviceCommand::cache_duration] of the command
+    durations: HashMap<CompactString, Duration>,
 }
 
 /// Cached value inside of [ResponseCache] for caching
@@ -53,8 +237,31 @@ struct CachedValue {
 }
 
 impl ResponseCache {
-    /// Time responses should be cached for before expiring
-    const CACHE_TIME: Duration = Duration::from_secs(1);
+    /// Default time responses should be cached for before expiring
+    pub const CACHE_TIME: Duration = Duration::from_secs(1);
+
+    /// Creates a cache using the provided durations to cache the responses
+    /// for each command instead of the command default
+    pub fn with_durations<I>(durations: I) -> Self
+    where
+        I: IntoIterator<Item = (CompactString, Duration)>,
+    {
+        Self {
+            cache: Default::default(),
+            durations: durations.into_iter().collect(),
+        }
+    }
+
+    /// Duration to cache the response for the provided protocol command, falls
+    /// back to the duration for the `QS` protocol command it replaces then the
+    /// `default` when the duration is not overridden
+    pub fn duration(&self, command: &str, base_command: &str, default: Duration) -> Duration {
+        self.durations
+            .get(command)
+            .or_else(|| self.durations.get(base_command))
+            .copied()
+            .unwrap_or(default)
+    }
 
     /// Attempts to get an item from the response cache using its
     /// cache key
@@ -74,20 +281,26 @@ impl ResponseCache {
         value.cloned()
     }
 
-    /// Inserts a response into the cache using its cache key
-    pub fn insert<T>(&mut self, cache_key: u64, value: &T)
+    /// Inserts a response into the cache using its cache key, the
+    /// response will expire after the provided `duration`
+    pub fn insert<T>(&mut self, cache_key: u64, value: &T, duration: Duration)
     where
         T: Send + Clone + 'static,
     {
         let value = value.clone();
         let cache_value = CachedValue {
-            expires_at: Instant::now() + Self::CACHE_TIME,
+            expires_at: Instant::now() + duration,
             value: Box::new(value),
         };
 
         self.cache.insert(cache_key, cache_value);
     }
 
+    /// Removes all the cached values
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
     /// Removes any cached values for the provided cache key
     #[inline]
     pub fn remove(&mut self, cache_key: u64) {
@@ -1,24 +1,54 @@
 //! PMV_H protocol
 
 use anyhow::Context;
+use chrono::{TimeDelta, Utc};
 use compact_str::{CompactString, format_compact};
 use ordered_float::OrderedFloat;
+use std::time::Duration;
 
 use super::{
-    command::{FromDeviceResponse, IntoDeviceCommand, ResponseCache},
-    models::{DeviceBattery, DeviceLineType, DevicePowerState, DeviceState},
+    command::{
+        CommandPriority, FromDeviceResponse, IntoDeviceCommand, ParseErrorKind, ResponseCache,
+        ResponseFields, ResponseParseError,
+    },
+    control::ControlState,
+    models::{
+        BatteryTestMode, DeviceBattery, DeviceInfo, DeviceLineType, DevicePowerState, DeviceRating,
+        DeviceState, PendingUPSShutdown, UPSShutdownRestore,
+    },
+    protocol::Protocol,
 };
 
+/// Time to cache responses for values that don't change while the
+/// device is running (Device info and ratings)
+const STATIC_CACHE_TIME: Duration = Duration::from_secs(60 * 60);
+
 /// Query command to load the device battery
 pub struct QueryDeviceBattery;
 
 impl IntoDeviceCommand for QueryDeviceBattery {
     type Response = DeviceBattery;
 
+    fn name(&self) -> &'static str {
+        "device_battery"
+    }
+
     fn get_command(&self) -> CompactString {
         "QI".into()
     }
 
+    fn get_protocol_command(&self, protocol: Protocol) -> CompactString {
+        protocol.battery_command().into()
+    }
+
+    fn parse_response(
+        &self,
+        protocol: Protocol,
+        value: CompactString,
+    ) -> anyhow::Result<Self::Response> {
+        protocol.parse_battery(value)
+    }
+
     fn cache_key(&self) -> Option<u64> {
         Some(0)
     }
@@ -27,19 +57,10 @@ impl IntoDeviceCommand for QueryDeviceBattery {
 impl FromDeviceResponse for DeviceBattery {
     fn from_device_response(msg: CompactString) -> anyhow::Result<Self> {
         // 100 02832 50.0 000.5 175 290 0 0000020000112000
-        let msg: &str = msg
-            .strip_prefix('(')
-            .context("Missing device battery response prefix")?;
-
-        let mut parts = msg.split(' ');
-
-        let capacity_str = parts.next().context("Missing capacity value")?;
-        let remaining_str = parts.next().context("Missing battery remaining value")?;
+        let mut fields = ResponseFields::new(&msg, '(')?;
 
-        let capacity = capacity_str.parse().context("Invalid capacity value")?;
-        let remaining_time = remaining_str
-            .parse()
-            .context("Invalid battery remaining value")?;
+        let capacity = fields.next("capacity")?;
+        let remaining_time = fields.next("battery remaining")?;
 
         Ok(DeviceBattery {
             capacity,
@@ -74,10 +95,26 @@ pub struct QueryDeviceState;
 impl IntoDeviceCommand for QueryDeviceState {
     type Response = DeviceState;
 
+    fn name(&self) -> &'static str {
+        "device_state"
+    }
+
     fn get_command(&self) -> CompactString {
         "QS".into()
     }
 
+    fn get_protocol_command(&self, protocol: Protocol) -> CompactString {
+        protocol.state_command().into()
+    }
+
+    fn parse_response(
+        &self,
+        protocol: Protocol,
+        value: CompactString,
+    ) -> anyhow::Result<Self::Response> {
+        protocol.parse_state(value)
+    }
+
     fn cache_key(&self) -> Option<u64> {
         Some(1)
     }
@@ -85,44 +122,20 @@ impl IntoDeviceCommand for QueryDeviceState {
 
 impl FromDeviceResponse for DeviceState {
     fn from_device_response(msg: CompactString) -> anyhow::Result<Self> {
-        let msg = msg
-            .strip_prefix('(')
-            .context("Missing device battery response prefix")?;
-
-        let mut parts = msg.split(' ');
-
-        let input_voltage: OrderedFloat<f64> = parts
-            .next()
-            .context("Missing input voltage")?
-            .parse()
-            .context("Invalid input voltage")?;
-        let _ = parts.next().context("Missing value 2")?;
-        let output_voltage: OrderedFloat<f64> = parts
-            .next()
-            .context("Missing output voltage")?
-            .parse()
-            .context("Invalid output voltage")?;
-        let output_load_percent: u8 = parts
-            .next()
-            .context("Missing output load percent")?
-            .parse()
-            .context("Invalid output load percent")?;
-        let output_frequency: OrderedFloat<f64> = parts
-            .next()
-            .context("Missing output frequency")?
-            .parse()
-            .context("Invalid output frequency")?;
-        let battery_voltage: OrderedFloat<f64> = parts
-            .next()
-            .context("Missing battery voltage")?
-            .parse()
-            .context("Invalid battery voltage")?;
-
-        let _ = parts.next().context("Missing value 7")?;
-        let status = parts.next().context("Missing value status")?;
+        // 237.1 237.1 237.1 008 50.1 27.1 --.- 00001001
+        let mut fields = ResponseFields::new(&msg, '(')?;
+
+        let input_voltage: OrderedFloat<f64> = fields.next("input voltage")?;
+        let input_fault_voltage = fields.next_optional("input fault voltage")?;
+        let output_voltage: OrderedFloat<f64> = fields.next("output voltage")?;
+        let output_load_percent: u8 = fields.next("output load percent")?;
+        let output_frequency: OrderedFloat<f64> = fields.next("output frequency")?;
+        let battery_voltage: OrderedFloat<f64> = fields.next("battery voltage")?;
+        let temperature = fields.next_optional("temperature")?;
+        let status = fields.next_str("status")?;
 
         if status.len() < 8 {
-            return Err(anyhow::anyhow!("Status had invalid length"));
+            return Err(fields.invalid("status").into());
         }
 
         let mut status_bits = ['0'; 8];
@@ -137,17 +150,17 @@ impl FromDeviceResponse for DeviceState {
 
         let status_bit_utility = status_bits[0];
         let status_bit_battery_low = status_bits[1];
-        let _status_bit_2 = status_bits[2];
+        let status_bit_bypass_boost = status_bits[2];
         let status_bit_fault_mode = status_bits[3];
         let status_bit_device_type = status_bits[4];
         let status_bit_self_test_progress = status_bits[5];
-        let _status_bit_6 = status_bits[6];
+        let status_bit_shutdown_active = status_bits[6];
         let status_bit_buzzer_control = status_bits[7];
 
         let device_power_state = match status_bit_utility {
             '0' => DevicePowerState::Utility,
             '1' => DevicePowerState::Battery,
-            _ => return Err(anyhow::anyhow!("Invalid device type status")),
+            _ => return Err(fields.invalid("status").into()),
         };
 
         let battery_low = status_bit_battery_low == '1';
@@ -156,34 +169,151 @@ impl FromDeviceResponse for DeviceState {
         let device_line_type = match status_bit_device_type {
             '0' => DeviceLineType::OnLine,
             '1' => DeviceLineType::LineInteractive,
-            _ => return Err(anyhow::anyhow!("Invalid device type status")),
+            _ => return Err(fields.invalid("status").into()),
         };
 
+        let bypass_boost_active = status_bit_bypass_boost == '1';
         let battery_self_test = status_bit_self_test_progress == '1';
+        let shutdown_active = status_bit_shutdown_active == '1';
         let buzzer_control = status_bit_buzzer_control == '1';
 
         Ok(DeviceState {
             input_voltage,
+            input_fault_voltage,
             output_voltage,
             output_load_percent,
             output_frequency,
             battery_voltage,
+            temperature,
             device_power_state,
             battery_low,
+            bypass_boost_active,
             fault_mode,
             device_line_type,
             battery_self_test,
+            shutdown_active,
             buzzer_control,
         })
     }
 }
 
+/// Query command to load the device company, model and version
+pub struct QueryDeviceInfo;
+
+impl IntoDeviceCommand for QueryDeviceInfo {
+    type Response = DeviceInfo;
+
+    fn name(&self) -> &'static str {
+        "device_info"
+    }
+
+    fn get_command(&self) -> CompactString {
+        "I".into()
+    }
+
+    fn parse_response(
+        &self,
+        protocol: Protocol,
+        value: CompactString,
+    ) -> anyhow::Result<Self::Response> {
+        protocol.parse_info(value)
+    }
+
+    fn cache_key(&self) -> Option<u64> {
+        Some(2)
+    }
+
+    fn cache_duration(&self) -> Duration {
+        STATIC_CACHE_TIME
+    }
+}
+
+impl FromDeviceResponse for DeviceInfo {
+    fn from_device_response(msg: CompactString) -> anyhow::Result<Self> {
+        // #Company_Name    UPS_Model  Version
+        let value: &str = msg
+            .strip_prefix('#')
+            .ok_or_else(|| ResponseParseError::new(&msg, "prefix", 0, ParseErrorKind::Missing))?;
+
+        // Fields are fixed width (15, 10 and 10 characters) separated by spaces
+        let mut chars = value.chars();
+        let mut next_field = |width: usize| -> String {
+            let value: String = chars.by_ref().take(width).collect();
+            // Skip the separator
+            _ = chars.next();
+            value.trim().to_string()
+        };
+
+        let company = next_field(15);
+        let model = next_field(10);
+        let version = next_field(10);
+
+        if company.is_empty() && model.is_empty() && version.is_empty() {
+            return Err(
+                ResponseParseError::new(&msg, "company", 0, ParseErrorKind::Missing).into(),
+            );
+        }
+
+        Ok(DeviceInfo {
+            company,
+            model,
+            version,
+        })
+    }
+}
+
+/// Query command to load the device rated values
+pub struct QueryDeviceRating;
+
+impl IntoDeviceCommand for QueryDeviceRating {
+    type Response = DeviceRating;
+
+    fn name(&self) -> &'static str {
+        "device_rating"
+    }
+
+    fn get_command(&self) -> CompactString {
+        "F".into()
+    }
+
+    fn cache_key(&self) -> Option<u64> {
+        Some(3)
+    }
+
+    fn cache_duration(&self) -> Duration {
+        STATIC_CACHE_TIME
+    }
+}
+
+impl FromDeviceResponse for DeviceRating {
+    fn from_device_response(msg: CompactString) -> anyhow::Result<Self> {
+        // #220.0 000 024.0 50.0
+        let mut fields = ResponseFields::new(&msg, '#')?;
+
+        let voltage = fields.next("rated voltage")?;
+        let current = fields.next("rated current")?;
+        let battery_voltage = fields.next("rated battery voltage")?;
+        let frequency = fields.next("rated frequency")?;
+
+        Ok(DeviceRating {
+            voltage,
+            current,
+            battery_voltage,
+            frequency,
+        })
+    }
+}
+
 /// Command to cancel a battery test
 pub struct CancelBatteryTest;
 
 impl IntoDeviceCommand for CancelBatteryTest {
     type Response = ExecuteResponse;
 
+    fn name(&self) -> &'static str {
+        "cancel_battery_test"
+    }
+
     fn get_command(&self) -> CompactString {
         "CT".into()
     }
@@ -196,6 +326,14 @@ impl IntoDeviceCommand for CancelBatteryTest {
             cache.remove(cache_key);
         }
     }
+
+    fn update_control_state(&self, state: &mut ControlState) {
+        state.battery_test = None;
+    }
+
+    fn priority(&self) -> CommandPriority {
+        CommandPriority::Control
+    }
 }
 
 /// Runs a 10s battery test
@@ -204,6 +342,10 @@ pub struct BatteryTest;
 impl IntoDeviceCommand for BatteryTest {
     type Response = ();
 
+    fn name(&self) -> &'static str {
+        "battery_test"
+    }
+
     fn get_command(&self) -> CompactString {
         "T".into()
     }
@@ -216,6 +358,85 @@ impl IntoDeviceCommand for BatteryTest {
             cache.remove(cache_key);
         }
     }
+
+    fn update_control_state(&self, state: &mut ControlState) {
+        state.battery_test = Some(BatteryTestMode::Quick);
+    }
+
+    fn priority(&self) -> CommandPriority {
+        CommandPriority::Control
+    }
+}
+
+/// Runs a battery test for a number of minutes
+pub struct BatteryTestMinutes {
+    /// Minutes to run the test for (1-99)
+    pub minutes: u8,
+}
+
+impl IntoDeviceCommand for BatteryTestMinutes {
+    type Response = ();
+
+    fn name(&self) -> &'static str {
+        "battery_test_minutes"
+    }
+
+    fn get_command(&self) -> CompactString {
+        let minutes = self.minutes.clamp(1, 99);
+
+        format_compact!("T{:02}", minutes)
+    }
+
+    fn invalidate_cache(&self, cache: &mut ResponseCache) {
+        // Clear the device state cache
+        let query = QueryDeviceState;
+
+        if let Some(cache_key) = query.cache_key() {
+            cache.remove(cache_key);
+        }
+    }
+
+    fn update_control_state(&self, state: &mut ControlState) {
+        state.battery_test = Some(BatteryTestMode::Minutes {
+            minutes: self.minutes.clamp(1, 99),
+        });
+    }
+
+    fn priority(&self) -> CommandPriority {
+        CommandPriority::Control
+    }
+}
+
+/// Runs a battery test until the battery is low
+pub struct BatteryTestUntilLow;
+
+impl IntoDeviceCommand for BatteryTestUntilLow {
+    type Response = ();
+
+    fn name(&self) -> &'static str {
+        "battery_test_until_low"
+    }
+
+    fn get_command(&self) -> CompactString {
+        "TL".into()
+    }
+
+    fn invalidate_cache(&self, cache: &mut ResponseCache) {
+        // Clear the device state cache
+        let query = QueryDeviceState;
+
+        if let Some(cache_key) = query.cache_key() {
+            cache.remove(cache_key);
+        }
+    }
+
+    fn update_control_state(&self, state: &mut ControlState) {
+        state.battery_test = Some(BatteryTestMode::UntilLow);
+    }
+
+    fn priority(&self) -> CommandPriority {
+        CommandPriority::Control
+    }
 }
 
 /// Command to trigger a delayed shutdown of the UPS and an
@@ -224,18 +445,113 @@ pub struct ScheduleUPSShutdown {
     /// Minutes to wait before shutting down
     pub delay_minutes: f32,
 
-    /// Delay in minutes before turning back on
-    pub reboot_delay_minutes: u16,
+    /// What the UPS should do after shutting down
+    pub restore: UPSShutdownRestore,
+}
+
+impl ScheduleUPSShutdown {
+    /// Shortest shutdown delay the device supports
+    pub const MIN_DELAY_MINUTES: f32 = 0.2;
+
+    /// Longest shutdown delay the device supports
+    pub const MAX_DELAY_MINUTES: f32 = 10.0;
+
+    /// Delay the device will actually use, the protocol only supports
+    /// tenths of a minute below one minute and whole minutes above
+    pub fn effective_delay_minutes(&self) -> f32 {
+        let delay_minutes = self
+            .delay_minutes
+            .clamp(Self::MIN_DELAY_MINUTES, Self::MAX_DELAY_MINUTES);
+
+        if delay_minutes < 1.0 {
+            (delay_minutes * 10.0).round() / 10.0
+        } else {
+            delay_minutes.round()
+        }
+    }
 }
 
 impl IntoDeviceCommand for ScheduleUPSShutdown {
     type Response = ();
 
+    fn name(&self) -> &'static str {
+        "schedule_ups_shutdown"
+    }
+
     fn get_command(&self) -> CompactString {
-        let delay_minutes = self.delay_minutes.min(9999.0);
-        let reboot_delay_minutes = self.reboot_delay_minutes.min(9999);
+        let delay_minutes = self.effective_delay_minutes();
+        let delay = if delay_minutes < 1.0 {
+            format_compact!(".{}", (delay_minutes * 10.0).round() as u8)
+        } else {
+            format_compact!("{:02}", delay_minutes as u8)
+        };
+
+        // Restoring after zero minutes tells the device to stay off
+        let restore_minutes = match self.restore {
+            UPSShutdownRestore::After { minutes } => minutes.clamp(1, 9999),
+            UPSShutdownRestore::StayOff => 0,
+        };
+
+        format_compact!("S{}R{:04}", delay, restore_minutes)
+    }
+
+    fn invalidate_cache(&self, cache: &mut ResponseCache) {
+        // Clear the device state cache
+        let query = QueryDeviceState;
 
-        format_compact!("S{}R{:04}", delay_minutes, reboot_delay_minutes)
+        if let Some(cache_key) = query.cache_key() {
+            cache.remove(cache_key);
+        }
+    }
+
+    fn update_control_state(&self, state: &mut ControlState) {
+        let delay_minutes = self.effective_delay_minutes();
+        let scheduled_at = Utc::now();
+        let shutdown_at =
+            scheduled_at + TimeDelta::milliseconds((delay_minutes * 60_000.0).round() as i64);
+
+        state.ups_shutdown = Some(PendingUPSShutdown {
+            delay_minutes: OrderedFloat(delay_minutes),
+            restore: self.restore,
+            scheduled_at,
+            shutdown_at,
+        });
+    }
+
+    fn priority(&self) -> CommandPriority {
+        CommandPriority::Control
+    }
+}
+
+/// Command to cancel a scheduled UPS shutdown
+pub struct CancelUPSShutdown;
+
+impl IntoDeviceCommand for CancelUPSShutdown {
+    type Response = ();
+
+    fn name(&self) -> &'static str {
+        "cancel_ups_shutdown"
+    }
+
+    fn get_command(&self) -> CompactString {
+        "C".into()
+    }
+
+    fn invalidate_cache(&self, cache: &mut ResponseCache) {
+        // Clear the device state cache
+        let query = QueryDeviceState;
+
+        if let Some(cache_key) = query.cache_key() {
+            cache.remove(cache_key);
+        }
+    }
+
+    fn update_control_state(&self, state: &mut ControlState) {
+        state.ups_shutdown = None;
+    }
+
+    fn priority(&self) -> CommandPriority {
+        CommandPriority::Control
     }
 }
 
@@ -246,6 +562,10 @@ pub struct ToggleBuzzer;
 impl IntoDeviceCommand for ToggleBuzzer {
     type Response = ();
 
+    fn name(&self) -> &'static str {
+        "toggle_buzzer"
+    }
+
     fn get_command(&self) -> CompactString {
         "Q".into()
     }
@@ -258,6 +578,38 @@ impl IntoDeviceCommand for ToggleBuzzer {
             cache.remove(cache_key);
         }
     }
+
+    fn priority(&self) -> CommandPriority {
+        CommandPriority::Control
+    }
+}
+
+/// Sends an arbitrary command to the device providing the unparsed
+/// response, used by the raw command console
+pub struct RawCommand {
+    /// The command to send
+    pub command: CompactString,
+}
+
+impl IntoDeviceCommand for RawCommand {
+    type Response = CompactString;
+
+    fn name(&self) -> &'static str {
+        "raw"
+    }
+
+    fn get_command(&self) -> CompactString {
+        self.command.clone()
+    }
+
+    fn invalidate_cache(&self, cache: &mut ResponseCache) {
+        // Raw commands may change the device state
+        cache.clear();
+    }
+
+    fn priority(&self) -> CommandPriority {
+        CommandPriority::Control
+    }
 }
 
 #[cfg(test)]
@@ -265,9 +617,14 @@ mod test {
     use ordered_float::OrderedFloat;
 
     use crate::ups::{
-        command::FromDeviceResponse,
-        models::{DeviceBattery, DeviceLineType, DevicePowerState, DeviceState},
+        BatteryTestMinutes, BatteryTestUntilLow, ScheduleUPSShutdown, UPSShutdownRestore,
+        command::{FromDeviceResponse, IntoDeviceCommand, ParseErrorKind, ResponseParseError},
+        models::{
+            BatteryTestMode, DeviceBattery, DeviceInfo, DeviceLineType, DevicePowerState,
+            DeviceRating, DeviceState,
+        },
     };
+    use garde::Validate;
 
     /// Should parse a valid device battery state
     #[test]
@@ -290,6 +647,32 @@ mod test {
         DeviceBattery::from_device_response(value.into()).expect_err("Battery should fail parsing");
     }
 
+    /// Parse errors should keep the raw response and the field that failed
+    #[test]
+    fn test_parse_error_field() {
+        let value = "(237.1 237.1 237.1 008 50.1 2?.1 --.- 00001001";
+        let err =
+            DeviceState::from_device_response(value.into()).expect_err("State should fail parsing");
+        let err = err
+            .downcast_ref::<ResponseParseError>()
+            .expect("Error should be a parse error");
+
+        assert_eq!(err.response, value);
+        assert_eq!(err.field, "battery voltage");
+        assert_eq!(err.index, 5);
+        assert_eq!(err.kind, ParseErrorKind::Invalid);
+
+        let err = DeviceBattery::from_device_response("(100".into())
+            .expect_err("Battery should fail parsing");
+        let err = err
+            .downcast_ref::<ResponseParseError>()
+            .expect("Error should be a parse error");
+
+        assert_eq!(err.field, "battery remaining");
+        assert_eq!(err.index, 1);
+        assert_eq!(err.kind, ParseErrorKind::Missing);
+    }
+
     /// Should parse a valid device state
     #[test]
     fn test_parse_device_state() {
@@ -298,34 +681,142 @@ mod test {
             .expect("Battery should parse successfully");
         let expected = DeviceState {
             input_voltage: OrderedFloat(237.1),
+            input_fault_voltage: Some(OrderedFloat(237.1)),
             output_voltage: OrderedFloat(237.1),
             output_load_percent: 8,
             output_frequency: OrderedFloat(50.1),
             battery_voltage: OrderedFloat(27.1),
+            temperature: None,
             device_power_state: DevicePowerState::Utility,
             battery_low: false,
+            bypass_boost_active: false,
             fault_mode: false,
             device_line_type: DeviceLineType::LineInteractive,
             battery_self_test: false,
+            shutdown_active: false,
             buzzer_control: true,
         };
         assert_eq!(battery.input_voltage, expected.input_voltage);
+        assert_eq!(battery.input_fault_voltage, expected.input_fault_voltage);
         assert_eq!(battery.output_voltage, expected.output_voltage);
         assert_eq!(battery.output_load_percent, expected.output_load_percent);
         assert_eq!(battery.output_frequency, expected.output_frequency);
         assert_eq!(battery.battery_voltage, expected.battery_voltage);
+        assert_eq!(battery.temperature, expected.temperature);
         assert_eq!(battery.device_power_state, expected.device_power_state);
         assert_eq!(battery.battery_low, expected.battery_low);
+        assert_eq!(battery.bypass_boost_active, expected.bypass_boost_active);
         assert_eq!(battery.fault_mode, expected.fault_mode);
         assert_eq!(battery.device_line_type, expected.device_line_type);
         assert_eq!(battery.battery_self_test, expected.battery_self_test);
+        assert_eq!(battery.shutdown_active, expected.shutdown_active);
         assert_eq!(battery.buzzer_control, expected.buzzer_control);
     }
 
+    /// Should parse the optional fields and status bits of a device state
+    #[test]
+    fn test_parse_device_state_optional() {
+        let value = "(230.4 180.2 230.1 012 50.0 27.3 32.5 00100010";
+        let state = DeviceState::from_device_response(value.into())
+            .expect("State should parse successfully");
+        assert_eq!(state.input_fault_voltage, Some(OrderedFloat(180.2)));
+        assert_eq!(state.temperature, Some(OrderedFloat(32.5)));
+        assert!(state.bypass_boost_active);
+        assert!(state.shutdown_active);
+        assert!(!state.battery_self_test);
+        assert!(!state.buzzer_control);
+    }
+
     /// Should fail on a malformed response
     #[test]
     fn test_fail_parse_device_state() {
         let value = "(A B 237.1 008 50.1 27.1 --.- 00001001";
         DeviceState::from_device_response(value.into()).expect_err("Battery should fail parsing");
     }
+
+    /// Should parse a valid device info
+    #[test]
+    fn test_parse_device_info() {
+        let value = "#Dynamix         UPSD2000   V1.0      ";
+        let info =
+            DeviceInfo::from_device_response(value.into()).expect("Info should parse successfully");
+        let expected = DeviceInfo {
+            company: "Dynamix".to_string(),
+            model: "UPSD2000".to_string(),
+            version: "V1.0".to_string(),
+        };
+        assert_eq!(info, expected);
+    }
+
+    /// Should fail on a malformed response
+    #[test]
+    fn test_fail_parse_device_info() {
+        let value = "(ACK";
+        DeviceInfo::from_device_response(value.into()).expect_err("Info should fail parsing");
+    }
+
+    /// Should parse a valid device rating
+    #[test]
+    fn test_parse_device_rating() {
+        let value = "#220.0 000 024.0 50.0";
+        let rating = DeviceRating::from_device_response(value.into())
+            .expect("Rating should parse successfully");
+        let expected = DeviceRating {
+            voltage: OrderedFloat(220.0),
+            current: 0,
+            battery_voltage: OrderedFloat(24.0),
+            frequency: OrderedFloat(50.0),
+        };
+        assert_eq!(rating, expected);
+    }
+
+    /// Should fail on a malformed response
+    #[test]
+    fn test_fail_parse_device_rating() {
+        let value = "#A 000 024.0 50.0";
+        DeviceRating::from_device_response(value.into()).expect_err("Rating should fail parsing");
+    }
+
+    /// Should create the battery test commands for each test length
+    #[test]
+    fn test_battery_test_commands() {
+        assert_eq!(BatteryTestMinutes { minutes: 5 }.get_command(), "T05");
+        assert_eq!(BatteryTestMinutes { minutes: 45 }.get_command(), "T45");
+        assert_eq!(BatteryTestUntilLow.get_command(), "TL");
+    }
+
+    /// Should only allow timed battery tests between 1 and 99 minutes
+    #[test]
+    fn test_validate_battery_test_mode() {
+        assert!(BatteryTestMode::Minutes { minutes: 30 }.validate().is_ok());
+        assert!(BatteryTestMode::Minutes { minutes: 0 }.validate().is_err());
+        assert!(
+            BatteryTestMode::Minutes { minutes: 100 }
+                .validate()
+                .is_err()
+        );
+    }
+
+    /// Should create shutdown commands using the protocol delay and restore formats
+    #[test]
+    fn test_schedule_ups_shutdown_command() {
+        let command = ScheduleUPSShutdown {
+            delay_minutes: 0.5,
+            restore: UPSShutdownRestore::After { minutes: 3 },
+        };
+        assert_eq!(command.get_command(), "S.5R0003");
+
+        let command = ScheduleUPSShutdown {
+            delay_minutes: 2.0,
+            restore: UPSShutdownRestore::StayOff,
+        };
+        assert_eq!(command.get_command(), "S02R0000");
+
+        // Delays are clamped to the supported range
+        let command = ScheduleUPSShutdown {
+            delay_minutes: 60.0,
+            restore: UPSShutdownRestore::default(),
+        };
+        assert_eq!(command.get_command(), "S10R0001");
+    }
 }
@@ -0,0 +1,71 @@
+//! # Control State
+//!
+//! State about the control commands that have been sent to a device, updated
+//! by the executor as commands are executed. Allows services like the watcher
+//! to know why the device state changed (i.e which kind of battery test was
+//! requested)
+
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+
+use super::models::{BatteryTestMode, PendingUPSShutdown};
+
+/// Shared reference to a [ControlState]
+pub type SharedControlState = Arc<Mutex<ControlState>>;
+
+/// State updated by control commands sent to the device
+#[derive(Debug, Clone, Default)]
+pub struct ControlState {
+    /// Battery test that was last requested, [None] if no test has been
+    /// requested or the last test was cancelled
+    pub battery_test: Option<BatteryTestMode>,
+    /// UPS shutdown that was last scheduled, [None] if no shutdown has
+    /// been scheduled or the last shutdown was cancelled
+    pub ups_shutdown: Option<PendingUPSShutdown>,
+}
+
+impl ControlState {
+    /// Provides the scheduled UPS shutdown, clears the shutdown
+    /// once it has completed at the provided `now`
+    pub fn pending_ups_shutdown(&mut self, now: DateTime<Utc>) -> Option<PendingUPSShutdown> {
+        if self
+            .ups_shutdown
+            .is_some_and(|shutdown| shutdown.completed_at() <= now)
+        {
+            self.ups_shutdown = None;
+        }
+
+        self.ups_shutdown
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeDelta, Utc};
+    use ordered_float::OrderedFloat;
+
+    use super::ControlState;
+    use crate::ups::{PendingUPSShutdown, UPSShutdownRestore};
+
+    /// Shutdown should be cleared once the UPS has turned back on
+    #[test]
+    fn test_pending_ups_shutdown_completed() {
+        let scheduled_at = Utc::now();
+        let shutdown_at = scheduled_at + TimeDelta::minutes(1);
+        let mut state = ControlState {
+            ups_shutdown: Some(PendingUPSShutdown {
+                delay_minutes: OrderedFloat(1.0),
+                restore: UPSShutdownRestore::After { minutes: 2 },
+                scheduled_at,
+                shutdown_at,
+            }),
+            ..Default::default()
+        };
+
+        let restored_at = shutdown_at + TimeDelta::minutes(2);
+        assert!(state.pending_ups_shutdown(shutdown_at).is_some());
+        assert!(state.pending_ups_shutdown(restored_at).is_none());
+        assert!(state.ups_shutdown.is_none());
+    }
+}
@@ -0,0 +1,166 @@
+//! # HID Device
+//!
+//! USB HID transport for the UPS, used by the UPSD2000 which exposes
+//! the text protocol over HID reports
+
+use std::ffi::CString;
+
+use anyhow::Context;
+use compact_str::CompactString;
+use hidapi::{HidApi, HidError};
+
+use super::{Device, DeviceCreator};
+use crate::config::HidConfig;
+
+/// Size for the HID device read buffer
+const HID_READ_BUFFER_SIZE: usize = 128;
+
+/// Size for the HID device write buffer
+///
+/// Majority of writes are only going to use up 4 bytes but some larger
+/// timing related commands use a few extra bytes
+const HID_WRITE_BUFFER_SIZE: usize = 32;
+
+/// Native HID device with a buffer for reading
+pub struct HidDevice {
+    /// The underlying device
+    device: hidapi::HidDevice,
+    /// Buffer for reading from the device
+    read_buffer: [u8; HID_READ_BUFFER_SIZE],
+    /// Buffer for writing to the device
+    write_buffer: Vec<u8>,
+}
+
+pub struct HidDeviceCreator {
+    /// API to create the device with
+    api: HidApi,
+    /// How the device should be selected
+    selector: HidDeviceSelector,
+}
+
+/// Selector for choosing which HID device to open
+enum HidDeviceSelector {
+    /// Open the device at a specific path
+    Path(CString),
+    /// Open the device with a specific serial number
+    Serial {
+        vendor_id: u16,
+        product_id: u16,
+        serial_number: String,
+    },
+    /// Open the first device matching the IDs
+    Id { vendor_id: u16, product_id: u16 },
+}
+
+impl HidDeviceCreator {
+    pub fn new(config: &HidConfig) -> anyhow::Result<Self> {
+        let api = HidApi::new().context("failed to create hid api")?;
+
+        let selector = if let Some(path) = config.path.as_ref() {
+            let path = CString::new(path.as_str()).context("invalid hid device path")?;
+            HidDeviceSelector::Path(path)
+        } else if let Some(serial_number) = config.serial_number.as_ref() {
+            HidDeviceSelector::Serial {
+                vendor_id: config.vendor_id,
+                product_id: config.product_id,
+                serial_number: serial_number.clone(),
+            }
+        } else {
+            HidDeviceSelector::Id {
+                vendor_id: config.vendor_id,
+                product_id: config.product_id,
+            }
+        };
+
+        Ok(Self { api, selector })
+    }
+}
+
+impl DeviceCreator for HidDeviceCreator {
+    type Output = HidDevice;
+
+    fn try_create_device(&self) -> anyhow::Result<Self::Output> {
+        let device = match &self.selector {
+            HidDeviceSelector::Path(path) => self.api.open_path(path),
+            HidDeviceSelector::Serial {
+                vendor_id,
+                product_id,
+                serial_number,
+            } => self.api.open_serial(*vendor_id, *product_id, serial_number),
+            HidDeviceSelector::Id {
+                vendor_id,
+                product_id,
+            } => self.api.open(*vendor_id, *product_id),
+        }
+        .context("failed to open device")?;
+
+        Ok(HidDevice {
+            device,
+            read_buffer: [0u8; HID_READ_BUFFER_SIZE],
+            write_buffer: Vec::with_capacity(HID_WRITE_BUFFER_SIZE),
+        })
+    }
+}
+
+impl Device for HidDevice {
+    type Creator = HidDeviceCreator;
+
+    /// Sends a command over the device HID, commands begin with the report ID which
+    /// is always zero and end with a carriage return to indicate the end of a command
+    fn write_command(&mut self, cmd: &str) -> anyhow::Result<()> {
+        let write_buffer = &mut self.write_buffer;
+
+        write_buffer.push(0); // Report ID
+        write_buffer.extend_from_slice(cmd.as_bytes());
+        write_buffer.push(b'\r');
+
+        self.device.write(write_buffer)?;
+
+        // Reset the write buffer
+        write_buffer.clear();
+
+        Ok(())
+    }
+
+    /// Reads a response from the device, terminated by a carriage return
+    /// or 3s timeout
+    fn read_response(&mut self) -> anyhow::Result<CompactString> {
+        let mut out = CompactString::default();
+
+        loop {
+            let count = self
+                .device
+                .read_timeout(&mut self.read_buffer, 3000)
+                .context("Failed to read response")?;
+
+            if count == 0 {
+                return Ok(out);
+            }
+
+            let chars = self
+                .read_buffer
+                .iter()
+                // Take only available length
+                .take(count)
+                // Bytes are characters
+                .map(|value| *value as char);
+
+            for char in chars {
+                // Break response at carriage return
+                if char == '\r' {
+                    return Ok(out);
+                }
+
+                out.push(char);
+            }
+        }
+    }
+
+    fn is_disconnect_error(&self, err: &anyhow::Error) -> bool {
+        err
+            // Try downcast to a HID error
+            .downcast_ref::<HidError>()
+            // Check if the error contains the disconnected message
+            .is_some_and(|value| value.to_string().contains("The device is not connected"))
+    }
+}
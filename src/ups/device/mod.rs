@@ -0,0 +1,300 @@
+//! # Device
+//!
+//! Transports for communicating with the UPS, each transport implements
+//! [Device] and a matching [DeviceCreator] so it can be used by the
+//! [DeviceExecutor](super::DeviceExecutor)
+
+use anyhow::Context;
+use compact_str::CompactString;
+
+use super::protocol::Protocol;
+use crate::config::{DeviceConfig, DeviceKind};
+
+mod hid;
+mod nut;
+mod recording;
+mod replay;
+mod serial;
+mod simulated;
+
+pub use hid::{HidDevice, HidDeviceCreator};
+pub use nut::{NutDevice, NutDeviceCreator};
+pub use recording::{CaptureEntry, RecordingDevice, RecordingDeviceCreator};
+pub use replay::{ReplayDevice, ReplayDeviceCreator};
+pub use serial::{SerialDevice, SerialDeviceCreator};
+pub use simulated::{
+    SharedSimulation, SimulatedDevice, SimulatedDeviceCreator, Simulation, SimulationStatus,
+};
+
+pub type DefaultDevice = AnyDevice;
+
+/// Creator for devices
+pub trait DeviceCreator: Sized + Send + 'static {
+    type Output: Device;
+
+    fn try_create_device(&self) -> anyhow::Result<Self::Output>;
+}
+
+/// Trait implemented by a device that can execute commands
+pub trait Device: Sized + Send + 'static {
+    type Creator: DeviceCreator<Output = Self>;
+
+    /// Sends a command to the device and receives the response
+    fn send_command(&mut self, cmd: &str) -> anyhow::Result<CompactString> {
+        self.write_command(cmd).context("write command")?;
+        self.read_response().context("read response")
+    }
+
+    /// Writes a command to the device
+    fn write_command(&mut self, cmd: &str) -> anyhow::Result<()>;
+
+    /// Reads a string response from the device
+    fn read_response(&mut self) -> anyhow::Result<CompactString>;
+
+    /// Checks if the provided error indicates the device was disconnected
+    /// and a new device must be created
+    fn is_disconnect_error(&self, _err: &anyhow::Error) -> bool {
+        false
+    }
+
+    /// Protocol the device is known to speak, devices that don't
+    /// know their protocol are probed when they are connected
+    fn protocol(&self) -> Option<Protocol> {
+        None
+    }
+}
+
+/// Device using the transport chosen by the user configuration
+pub enum AnyDevice {
+    Hid(HidDevice),
+    Serial(SerialDevice),
+    Simulated(SimulatedDevice),
+    Nut(NutDevice),
+    Replay(ReplayDevice),
+    Recording(Box<RecordingDevice<AnyDevice>>),
+}
+
+/// Creator for [AnyDevice] using the transport chosen by the user configuration
+pub enum AnyDeviceCreator {
+    Hid(HidDeviceCreator),
+    Serial(SerialDeviceCreator),
+    Simulated(SimulatedDeviceCreator),
+    Nut(NutDeviceCreator),
+    Replay(ReplayDeviceCreator),
+    Recording(Box<RecordingDeviceCreator<AnyDeviceCreator>>),
+}
+
+impl AnyDeviceCreator {
+    /// Creates the device creator for the transport from the provided config,
+    /// wrapping it to record the device traffic when a capture file is set
+    pub fn from_config(config: &DeviceConfig) -> anyhow::Result<Self> {
+        let creator = match config.kind {
+            DeviceKind::Hid => Self::Hid(HidDeviceCreator::new(&config.hid)?),
+            DeviceKind::Serial => Self::Serial(SerialDeviceCreator::new(&config.serial)?),
+            DeviceKind::Simulated => {
+                Self::Simulated(SimulatedDeviceCreator::new(&config.simulated))
+            }
+            DeviceKind::Nut => Self::Nut(NutDeviceCreator::new(&config.nut)),
+            DeviceKind::Replay => Self::Replay(ReplayDeviceCreator::new(&config.replay)?),
+        };
+
+        Ok(match config.record.as_ref() {
+            Some(path) => Self::Recording(Box::new(RecordingDeviceCreator::new(creator, path)?)),
+            None => creator,
+        })
+    }
+
+    /// Simulation used by the created devices, only available
+    /// for simulated devices
+    pub fn simulation(&self) -> Option<SharedSimulation> {
+        match self {
+            Self::Simulated(creator) => Some(creator.simulation().clone()),
+            Self::Recording(creator) => creator.creator().simulation(),
+            _ => None,
+        }
+    }
+}
+
+impl DeviceCreator for AnyDeviceCreator {
+    type Output = AnyDevice;
+
+    fn try_create_device(&self) -> anyhow::Result<Self::Output> {
+        Ok(match self {
+            Self::Hid(creator) => AnyDevice::Hid(creator.try_create_device()?),
+            Self::Serial(creator) => AnyDevice::Serial(creator.try_create_device()?),
+            Self::Simulated(creator) => AnyDevice::Simulated(creator.try_create_device()?),
+            Self::Nut(creator) => AnyDevice::Nut(creator.try_create_device()?),
+            Self::Replay(creator) => AnyDevice::Replay(creator.try_create_device()?),
+            Self::Recording(creator) => {
+                AnyDevice::Recording(Box::new(creator.try_create_device()?))
+            }
+        })
+    }
+}
+
+impl Device for AnyDevice {
+    type Creator = AnyDeviceCreator;
+
+    fn write_command(&mut self, cmd: &str) -> anyhow::Result<()> {
+        match self {
+            Self::Hid(device) => device.write_command(cmd),
+            Self::Serial(device) => device.write_command(cmd),
+            Self::Simulated(device) => device.write_command(cmd),
+            Self::Nut(device) => device.write_command(cmd),
+            Self::Replay(device) => device.write_command(cmd),
+            Self::Recording(device) => device.write_command(cmd),
+        }
+    }
+
+    fn read_response(&mut self) -> anyhow::Result<CompactString> {
+        match self {
+            Self::Hid(device) => device.read_response(),
+            Self::Serial(device) => device.read_response(),
+            Self::Simulated(device) => device.read_response(),
+            Self::Nut(device) => device.read_response(),
+            Self::Replay(device) => device.read_response(),
+            Self::Recording(device) => device.read_response(),
+        }
+    }
+
+    fn is_disconnect_error(&self, err: &anyhow::Error) -> bool {
+        match self {
+            Self::Hid(device) => device.is_disconnect_error(err),
+            Self::Serial(device) => device.is_disconnect_error(err),
+            Self::Simulated(device) => device.is_disconnect_error(err),
+            Self::Nut(device) => device.is_disconnect_error(err),
+            Self::Replay(device) => device.is_disconnect_error(err),
+            Self::Recording(device) => device.is_disconnect_error(err),
+        }
+    }
+
+    fn protocol(&self) -> Option<Protocol> {
+        match self {
+            Self::Hid(device) => device.protocol(),
+            Self::Serial(device) => device.protocol(),
+            Self::Simulated(device) => device.protocol(),
+            Self::Nut(device) => device.protocol(),
+            Self::Replay(device) => device.protocol(),
+            Self::Recording(device) => device.protocol(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::{Context, anyhow};
+    use compact_str::CompactString;
+    use tokio::sync::broadcast;
+    use tokio::sync::mpsc;
+
+    use super::Device;
+    use super::DeviceCreator;
+    use crate::ups::protocol::Protocol;
+
+    /// Mocking responder implementation that lets you set
+    /// the next response the [MockDevice] will respond with
+    pub struct MockDeviceHandle {
+        /// Sender for the next response
+        tx: broadcast::Sender<CompactString>,
+        /// Receiver for the next command
+        rx: mpsc::Receiver<CompactString>,
+    }
+
+    impl MockDeviceHandle {
+        /// Set the next response
+        pub fn next_response(&self, next: CompactString) {
+            _ = self.tx.send(next);
+        }
+
+        /// Await the next executed command
+        pub async fn next_command(&mut self) -> Option<CompactString> {
+            self.rx.recv().await
+        }
+
+        /// Takes the next executed command if one has been executed
+        pub fn try_next_command(&mut self) -> Option<CompactString> {
+            self.rx.try_recv().ok()
+        }
+    }
+
+    /// Creator for creating [MockDevice]s that will respond using
+    /// the provided shared responder
+    pub struct MockDeviceCreator {
+        /// Sender for command messages
+        tx: mpsc::Sender<CompactString>,
+        /// Receiver for responses, shared by the created devices so responses
+        /// set before a device is created are not missed
+        rx: Arc<Mutex<broadcast::Receiver<CompactString>>>,
+        /// Protocol the created devices report
+        protocol: Option<Protocol>,
+    }
+
+    impl MockDeviceCreator {
+        /// Create a new creator, provides the creator itself and the responder handle
+        pub fn new() -> (Self, MockDeviceHandle) {
+            let (tx_cmd, rx_cmd) = mpsc::channel(8);
+            let (tx_res, rx_res) = broadcast::channel(8);
+            let handle: MockDeviceHandle = MockDeviceHandle {
+                tx: tx_res,
+                rx: rx_cmd,
+            };
+            let creator = Self {
+                tx: tx_cmd,
+                rx: Arc::new(Mutex::new(rx_res)),
+                protocol: Some(Protocol::Qs),
+            };
+
+            (creator, handle)
+        }
+
+        /// Sets the protocol the created devices report, [None]
+        /// requires the executor to probe the device
+        pub fn with_protocol(mut self, protocol: Option<Protocol>) -> Self {
+            self.protocol = protocol;
+            self
+        }
+    }
+
+    impl DeviceCreator for MockDeviceCreator {
+        type Output = MockDevice;
+        fn try_create_device(&self) -> anyhow::Result<Self::Output> {
+            Ok(MockDevice {
+                tx: self.tx.clone(),
+                rx: self.rx.clone(),
+                protocol: self.protocol,
+            })
+        }
+    }
+
+    pub struct MockDevice {
+        /// Sender for command messages
+        tx: mpsc::Sender<CompactString>,
+        /// Receiver for responses
+        rx: Arc<Mutex<broadcast::Receiver<CompactString>>>,
+        /// Protocol the device reports
+        protocol: Option<Protocol>,
+    }
+
+    impl Device for MockDevice {
+        type Creator = MockDeviceCreator;
+
+        fn read_response(&mut self) -> anyhow::Result<CompactString> {
+            self.rx
+                .lock()
+                .map_err(|_| anyhow!("mock receiver poisoned"))?
+                .blocking_recv()
+                .context("missing response")
+        }
+
+        fn write_command(&mut self, cmd: &str) -> anyhow::Result<()> {
+            _ = self.tx.blocking_send(cmd.into());
+            Ok(())
+        }
+
+        fn protocol(&self) -> Option<Protocol> {
+            self.protocol
+        }
+    }
+}
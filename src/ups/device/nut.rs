@@ -0,0 +1,474 @@
+//! # NUT Device
+//!
+//! Device that uses a remote NUT (Network UPS Tools) `upsd` as its data
+//! source, used when the UPS is connected to a different machine running
+//! NUT. The NUT variables for the UPS are translated into the responses the
+//! UPS would give for the query commands so the rest of the app can treat it
+//! like any other device.
+//!
+//! NUT devices are read-only, control commands are not supported
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use anyhow::{Context, anyhow};
+use compact_str::{CompactString, format_compact};
+
+use super::{Device, DeviceCreator};
+use crate::{
+    config::NutDeviceConfig,
+    nut::{parse_line, quote},
+    ups::protocol::Protocol,
+};
+
+/// Variables for a UPS on the remote server
+type NutVariables = HashMap<String, String>;
+
+/// Device connected to a remote NUT server
+pub struct NutDevice {
+    /// Reader for the server connection
+    reader: BufReader<TcpStream>,
+    /// Writer for the server connection
+    writer: TcpStream,
+    /// Name of the UPS on the remote server
+    ups: String,
+    /// Response to the last written command
+    response: Option<CompactString>,
+}
+
+/// Creator for [NutDevice]s, connects to the configured server
+pub struct NutDeviceCreator {
+    /// Host and port of the remote server
+    address: String,
+    /// Name of the UPS on the remote server
+    ups: String,
+    /// Optional credentials to log in to the UPS with
+    credentials: Option<(String, String)>,
+    /// Timeout for connecting and waiting for a response
+    timeout: Duration,
+}
+
+impl NutDeviceCreator {
+    pub fn new(config: &NutDeviceConfig) -> Self {
+        let credentials = config.username.clone().zip(config.password.clone());
+
+        Self {
+            address: format!("{}:{}", config.host, config.port),
+            ups: config.ups.clone(),
+            credentials,
+            timeout: Duration::from_millis(config.timeout_ms),
+        }
+    }
+}
+
+impl DeviceCreator for NutDeviceCreator {
+    type Output = NutDevice;
+
+    fn try_create_device(&self) -> anyhow::Result<Self::Output> {
+        let address = self
+            .address
+            .to_socket_addrs()
+            .context("failed to resolve nut server address")?
+            .next()
+            .context("nut server address did not resolve")?;
+
+        let stream = TcpStream::connect_timeout(&address, self.timeout)
+            .context("failed to connect to nut server")?;
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+
+        let mut device = NutDevice {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            ups: self.ups.clone(),
+            response: None,
+        };
+
+        // Log in so the server knows this client depends on the UPS
+        if let Some((username, password)) = self.credentials.as_ref() {
+            device.expect_ok(&format!("USERNAME {}", quote(username)))?;
+            device.expect_ok(&format!("PASSWORD {}", quote(password)))?;
+            device.expect_ok(&format!("LOGIN {}", quote(&device.ups)))?;
+        }
+
+        // Ensure the UPS exists on the server
+        device.list_variables().context("failed to load ups")?;
+
+        Ok(device)
+    }
+}
+
+impl NutDevice {
+    /// Writes a line to the server
+    fn write_line(&mut self, line: &str) -> anyhow::Result<()> {
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Reads a line from the server, fails if the server responded with an error
+    fn read_line(&mut self) -> anyhow::Result<String> {
+        let mut line = String::new();
+        let count = self.reader.read_line(&mut line)?;
+        if count == 0 {
+            return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+        }
+
+        let line = line.trim_end().to_string();
+        if let Some(error) = line.strip_prefix("ERR ") {
+            return Err(anyhow!("nut server error: {error}"));
+        }
+
+        Ok(line)
+    }
+
+    /// Writes a line and expects an `OK` response
+    fn expect_ok(&mut self, line: &str) -> anyhow::Result<()> {
+        self.write_line(line)?;
+
+        let response = self.read_line()?;
+        if !response.starts_with("OK") {
+            return Err(anyhow!("unexpected nut server response: {response}"));
+        }
+
+        Ok(())
+    }
+
+    /// Loads all the variables for the UPS
+    fn list_variables(&mut self) -> anyhow::Result<NutVariables> {
+        self.write_line(&format!("LIST VAR {}", quote(&self.ups)))?;
+
+        let begin = self.read_line()?;
+        if !begin.starts_with("BEGIN LIST VAR") {
+            return Err(anyhow!("unexpected nut server response: {begin}"));
+        }
+
+        let mut variables = NutVariables::new();
+
+        loop {
+            let line = self.read_line()?;
+            let args = parse_line(&line).context("invalid nut server response")?;
+
+            match args.as_slice() {
+                [var, _, name, value] if var == "VAR" => {
+                    variables.insert(name.clone(), value.clone());
+                }
+                [end, ..] if end == "END" => return Ok(variables),
+                _ => return Err(anyhow!("unexpected nut server response: {line}")),
+            }
+        }
+    }
+}
+
+impl Device for NutDevice {
+    type Creator = NutDeviceCreator;
+
+    fn write_command(&mut self, cmd: &str) -> anyhow::Result<()> {
+        let respond: fn(&NutVariables) -> anyhow::Result<CompactString> = match cmd {
+            "QS" => device_state_response,
+            "QI" => device_battery_response,
+            "I" => device_info_response,
+            "F" => device_rating_response,
+            _ => return Err(anyhow!("command not supported by nut devices: {cmd}")),
+        };
+
+        let variables = self.list_variables()?;
+        self.response = Some(respond(&variables)?);
+        Ok(())
+    }
+
+    fn read_response(&mut self) -> anyhow::Result<CompactString> {
+        self.response.take().context("no command was written")
+    }
+
+    /// Variables are always translated into NUT protocol responses
+    fn protocol(&self) -> Option<Protocol> {
+        Some(Protocol::Nut)
+    }
+
+    /// Connection errors and timeouts are treated as disconnects, after a timeout
+    /// a late response could be mistaken as the response to the next command
+    fn is_disconnect_error(&self, err: &anyhow::Error) -> bool {
+        err.chain()
+            .filter_map(|err| err.downcast_ref::<std::io::Error>())
+            .any(|err| {
+                matches!(
+                    err.kind(),
+                    ErrorKind::BrokenPipe
+                        | ErrorKind::ConnectionReset
+                        | ErrorKind::ConnectionAborted
+                        | ErrorKind::NotConnected
+                        | ErrorKind::UnexpectedEof
+                        | ErrorKind::TimedOut
+                        | ErrorKind::WouldBlock
+                )
+            })
+    }
+}
+
+/// Gets a numeric variable
+fn number(variables: &NutVariables, name: &str) -> anyhow::Result<Option<f64>> {
+    variables
+        .get(name)
+        .map(|value| value.parse::<f64>())
+        .transpose()
+        .with_context(|| format!("invalid nut variable {name}"))
+}
+
+/// Formats an optional value, missing values use dashes like the device does
+fn optional_value(value: Option<f64>) -> CompactString {
+    match value {
+        Some(value) => format_compact!("{value:05.1}"),
+        None => "---.-".into(),
+    }
+}
+
+/// Creates the QS device state response from the NUT variables,
+/// values the server doesn't provide are reported as zero
+fn device_state_response(variables: &NutVariables) -> anyhow::Result<CompactString> {
+    let status = variables
+        .get("ups.status")
+        .context("nut server did not provide ups.status")?;
+    let flags: Vec<&str> = status.split_whitespace().collect();
+    let has_flag = |flag: &str| flags.contains(&flag);
+
+    let input_voltage = number(variables, "input.voltage")?.unwrap_or_default();
+    let input_fault_voltage = number(variables, "input.voltage.fault")?;
+    let output_voltage = number(variables, "output.voltage")?.unwrap_or_default();
+    let load = number(variables, "ups.load")?.unwrap_or_default();
+    let frequency = match number(variables, "output.frequency")? {
+        Some(value) => value,
+        None => number(variables, "input.frequency")?.unwrap_or_default(),
+    };
+    let battery_voltage = number(variables, "battery.voltage")?.unwrap_or_default();
+    let temperature = number(variables, "ups.temperature")?;
+
+    let online = variables
+        .get("ups.type")
+        .is_some_and(|value| value == "online");
+    let test_running = has_flag("CAL")
+        || variables
+            .get("ups.test.result")
+            .is_some_and(|value| value.to_lowercase().contains("in progress"));
+    let beeper = variables
+        .get("ups.beeper.status")
+        .is_some_and(|value| value == "enabled");
+
+    let bit = |value: bool| if value { '1' } else { '0' };
+    let status: CompactString = [
+        bit(has_flag("OB")),
+        bit(has_flag("LB")),
+        bit(has_flag("BOOST") || has_flag("TRIM") || has_flag("BYPASS")),
+        bit(has_flag("ALARM")),
+        bit(!online),
+        bit(test_running),
+        bit(has_flag("FSD")),
+        bit(beeper),
+    ]
+    .into_iter()
+    .collect();
+
+    Ok(format_compact!(
+        "({:05.1} {} {:05.1} {:03} {:04.1} {:04.1} {} {}",
+        input_voltage,
+        optional_value(input_fault_voltage),
+        output_voltage,
+        load.round().clamp(0.0, 255.0) as u8,
+        frequency,
+        battery_voltage,
+        optional_value(temperature),
+        status
+    ))
+}
+
+/// Creates the QI device battery response from the NUT variables
+fn device_battery_response(variables: &NutVariables) -> anyhow::Result<CompactString> {
+    let charge = number(variables, "battery.charge")?
+        .context("nut server did not provide battery.charge")?;
+    let runtime = number(variables, "battery.runtime")?
+        .context("nut server did not provide battery.runtime")?;
+
+    Ok(format_compact!(
+        "({:03} {:05}",
+        charge.round().clamp(0.0, 100.0) as u8,
+        runtime.max(0.0) as u32
+    ))
+}
+
+/// Creates the I device info response from the NUT variables
+fn device_info_response(variables: &NutVariables) -> anyhow::Result<CompactString> {
+    let get = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| variables.get(*name))
+            .map(String::as_str)
+            .unwrap_or_default()
+    };
+
+    let company = get(&["device.mfr", "ups.mfr"]);
+    let model = get(&["device.model", "ups.model"]);
+    let version = get(&["ups.firmware"]);
+
+    // Fields are tab separated rather than fixed width so they aren't truncated
+    let field = |value: &str| -> String { value.replace('\t', " ") };
+
+    Ok(format_compact!(
+        "#{}\t{}\t{}",
+        field(company),
+        field(model),
+        field(version)
+    ))
+}
+
+/// Creates the F device rating response from the NUT variables
+fn device_rating_response(variables: &NutVariables) -> anyhow::Result<CompactString> {
+    let voltage = match number(variables, "output.voltage.nominal")? {
+        Some(value) => value,
+        None => number(variables, "input.voltage.nominal")?
+            .context("nut server did not provide nominal voltage")?,
+    };
+    let current = number(variables, "output.current.nominal")?.unwrap_or_default();
+    let battery_voltage = number(variables, "battery.voltage.nominal")?.unwrap_or_default();
+    let frequency = match number(variables, "output.frequency.nominal")? {
+        Some(value) => value,
+        None => number(variables, "input.frequency.nominal")?.unwrap_or_default(),
+    };
+
+    Ok(format_compact!(
+        "#{:05.1} {:03} {:05.1} {:04.1}",
+        voltage,
+        current.round() as u16,
+        battery_voltage,
+        frequency
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    use ordered_float::OrderedFloat;
+
+    use super::NutDeviceCreator;
+    use crate::{
+        config::NutDeviceConfig,
+        ups::{
+            DeviceBattery, DeviceLineType, DevicePowerState, DeviceRating, DeviceState, Protocol,
+            command::FromDeviceResponse,
+            device::{Device, DeviceCreator},
+        },
+    };
+
+    /// Variables reported by the fake server
+    const FAKE_VARIABLES: &[(&str, &str)] = &[
+        ("battery.charge", "18"),
+        ("battery.runtime", "245"),
+        ("battery.voltage", "12.4"),
+        ("battery.voltage.nominal", "12"),
+        ("device.mfr", "American Power Conversion"),
+        ("device.model", "Back-UPS 700"),
+        ("input.voltage", "0.0"),
+        ("output.frequency", "50"),
+        ("output.voltage", "230.4"),
+        ("output.voltage.nominal", "230"),
+        ("ups.beeper.status", "enabled"),
+        ("ups.firmware", "871.O2"),
+        ("ups.load", "23.5"),
+        ("ups.status", "OB DISCHRG LB"),
+    ];
+
+    /// Starts a fake upsd on a random port that serves a single client,
+    /// provides the port the server is listening on
+    fn start_fake_upsd() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut line = String::new();
+
+            while reader.read_line(&mut line).unwrap_or_default() > 0 {
+                let response = match line.trim_end() {
+                    "USERNAME \"monuser\"" | "PASSWORD \"secret\"" | "LOGIN \"ups\"" => {
+                        "OK\n".to_string()
+                    }
+                    "LIST VAR \"ups\"" => {
+                        let mut response = "BEGIN LIST VAR ups\n".to_string();
+                        for (name, value) in FAKE_VARIABLES {
+                            response.push_str(&format!("VAR ups {name} \"{value}\"\n"));
+                        }
+                        response.push_str("END LIST VAR ups\n");
+                        response
+                    }
+                    _ => "ERR UNKNOWN-COMMAND\n".to_string(),
+                };
+
+                writer.write_all(response.as_bytes()).unwrap();
+                line.clear();
+            }
+        });
+
+        port
+    }
+
+    /// NUT variables should be translated into the device responses
+    #[test]
+    fn test_nut_device() {
+        let port = start_fake_upsd();
+        let creator = NutDeviceCreator::new(&NutDeviceConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            username: Some("monuser".to_string()),
+            password: Some("secret".to_string()),
+            ..Default::default()
+        });
+
+        let mut device = creator.try_create_device().unwrap();
+
+        let state = DeviceState::from_device_response(device.send_command("QS").unwrap()).unwrap();
+        assert_eq!(state.device_power_state, DevicePowerState::Battery);
+        assert_eq!(state.device_line_type, DeviceLineType::LineInteractive);
+        assert_eq!(state.output_load_percent, 24);
+        assert_eq!(state.temperature, None);
+        assert!(state.battery_low);
+        assert!(state.buzzer_control);
+        assert!(!state.battery_self_test);
+
+        let battery =
+            DeviceBattery::from_device_response(device.send_command("QI").unwrap()).unwrap();
+        assert_eq!(
+            battery,
+            DeviceBattery {
+                capacity: 18,
+                remaining_time: 245
+            }
+        );
+
+        let info = Protocol::Nut
+            .parse_info(device.send_command("I").unwrap())
+            .unwrap();
+        assert_eq!(info.company, "American Power Conversion");
+        assert_eq!(info.model, "Back-UPS 700");
+        assert_eq!(info.version, "871.O2");
+
+        let rating = DeviceRating::from_device_response(device.send_command("F").unwrap()).unwrap();
+        assert_eq!(rating.voltage, OrderedFloat(230.0));
+
+        // Control commands are not supported
+        assert!(device.send_command("T").is_err());
+
+        // Server errors are not disconnects
+        device.ups = "missing".to_string();
+        let err = device.list_variables().unwrap_err();
+        assert!(!device.is_disconnect_error(&err));
+    }
+}
@@ -0,0 +1,175 @@
+//! # Recording Device
+//!
+//! Wrapper around any [Device] that appends every command sent to the device
+//! along with its response to a capture file. Captures are stored as JSON
+//! lines (One [CaptureEntry] per line) and can be played back later using a
+//! [ReplayDevice](super::ReplayDevice) to reproduce odd readings from a device
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, anyhow};
+use chrono::{DateTime, Utc};
+use compact_str::CompactString;
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use super::{Device, DeviceCreator};
+use crate::ups::protocol::Protocol;
+
+/// Shared writer for a capture file, shared between all the devices
+/// created by a [RecordingDeviceCreator] so reconnects keep appending
+/// to the same capture
+type SharedCaptureFile = Arc<Mutex<File>>;
+
+/// Single command and response pair within a capture file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureEntry {
+    /// When the command was written to the device
+    pub timestamp: DateTime<Utc>,
+    /// The command that was written
+    pub command: String,
+    /// The response from the device, [None] if the device failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+    /// Error from the device if writing the command or reading the response failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl CaptureEntry {
+    /// Converts the entry into the result the device produced
+    pub fn result(&self) -> anyhow::Result<CompactString> {
+        match (&self.response, &self.error) {
+            (_, Some(error)) => Err(anyhow!("{error}")),
+            (Some(response), None) => Ok(CompactString::new(response)),
+            (None, None) => Err(anyhow!("capture entry missing response")),
+        }
+    }
+}
+
+/// Device that records its traffic to a capture file
+pub struct RecordingDevice<D: Device> {
+    /// The device being recorded
+    device: D,
+    /// Capture file to append to
+    capture: SharedCaptureFile,
+    /// Command that was written and is waiting for a response
+    pending: Option<(DateTime<Utc>, String)>,
+}
+
+/// Creator for [RecordingDevice]s, wraps the devices created by another creator
+pub struct RecordingDeviceCreator<C: DeviceCreator> {
+    /// Creator for the underlying device
+    creator: C,
+    /// Capture file to append to
+    capture: SharedCaptureFile,
+}
+
+impl<C: DeviceCreator> RecordingDeviceCreator<C> {
+    /// Creates a new recording creator appending to the capture file at
+    /// the provided `path`, the file is created if it doesn't exist
+    pub fn new(creator: C, path: &Path) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open capture file: {}", path.display()))?;
+
+        Ok(Self {
+            creator,
+            capture: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Creator for the underlying device
+    pub fn creator(&self) -> &C {
+        &self.creator
+    }
+}
+
+impl<C: DeviceCreator> DeviceCreator for RecordingDeviceCreator<C> {
+    type Output = RecordingDevice<C::Output>;
+
+    fn try_create_device(&self) -> anyhow::Result<Self::Output> {
+        let device = self.creator.try_create_device()?;
+
+        Ok(RecordingDevice {
+            device,
+            capture: self.capture.clone(),
+            pending: None,
+        })
+    }
+}
+
+impl<D: Device> RecordingDevice<D> {
+    /// Appends the result for the pending command to the capture file
+    fn record(&mut self, result: Result<&CompactString, &anyhow::Error>) {
+        let Some((timestamp, command)) = self.pending.take() else {
+            return;
+        };
+
+        let (response, error) = match result {
+            Ok(response) => (Some(response.to_string()), None),
+            Err(err) => (None, Some(format!("{err:#}"))),
+        };
+
+        let entry = CaptureEntry {
+            timestamp,
+            command,
+            response,
+            error,
+        };
+
+        if let Err(err) = self.write_entry(&entry) {
+            error!("failed to write device capture: {err:#}");
+        }
+    }
+
+    /// Writes a single entry to the capture file
+    fn write_entry(&self, entry: &CaptureEntry) -> anyhow::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+
+        let mut capture = self
+            .capture
+            .lock()
+            .map_err(|_| anyhow!("capture file poisoned"))?;
+        capture.write_all(line.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+impl<D: Device> Device for RecordingDevice<D> {
+    type Creator = RecordingDeviceCreator<D::Creator>;
+
+    fn write_command(&mut self, cmd: &str) -> anyhow::Result<()> {
+        self.pending = Some((Utc::now(), cmd.to_string()));
+
+        let result = self.device.write_command(cmd);
+        if let Err(err) = result.as_ref() {
+            self.record(Err(err));
+        }
+
+        result
+    }
+
+    fn read_response(&mut self) -> anyhow::Result<CompactString> {
+        let result = self.device.read_response();
+        self.record(result.as_ref());
+        result
+    }
+
+    fn is_disconnect_error(&self, err: &anyhow::Error) -> bool {
+        self.device.is_disconnect_error(err)
+    }
+
+    fn protocol(&self) -> Option<Protocol> {
+        self.device.protocol()
+    }
+}
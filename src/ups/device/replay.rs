@@ -0,0 +1,230 @@
+//! # Replay Device
+//!
+//! Device that plays back a capture file written by a
+//! [RecordingDevice](super::RecordingDevice). Each command sent to the device
+//! is answered with the next response recorded for that same command, this
+//! allows a captured outage to run through the executor, watcher and pipelines
+//! for debugging and regression tests
+
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, anyhow};
+use compact_str::CompactString;
+
+use super::{Device, DeviceCreator, recording::CaptureEntry};
+use crate::{config::ReplayConfig, ups::protocol::Protocol};
+
+/// Shared reference to a [Replay]
+type SharedReplay = Arc<Mutex<Replay>>;
+
+/// Device that responds using a [Replay]
+pub struct ReplayDevice {
+    /// The replay to respond with
+    replay: SharedReplay,
+    /// Response to the last written command
+    response: Option<anyhow::Result<CompactString>>,
+}
+
+/// Creator for [ReplayDevice]s, all devices share the same replay so
+/// reconnecting continues from the current positions
+pub struct ReplayDeviceCreator {
+    /// The shared replay
+    replay: SharedReplay,
+}
+
+impl ReplayDeviceCreator {
+    pub fn new(config: &ReplayConfig) -> anyhow::Result<Self> {
+        let replay = Replay::from_file(&config.path, config.repeat)?;
+
+        Ok(Self {
+            replay: Arc::new(Mutex::new(replay)),
+        })
+    }
+}
+
+impl DeviceCreator for ReplayDeviceCreator {
+    type Output = ReplayDevice;
+
+    fn try_create_device(&self) -> anyhow::Result<Self::Output> {
+        Ok(ReplayDevice {
+            replay: self.replay.clone(),
+            response: None,
+        })
+    }
+}
+
+impl Device for ReplayDevice {
+    type Creator = ReplayDeviceCreator;
+
+    fn write_command(&mut self, cmd: &str) -> anyhow::Result<()> {
+        let mut replay = self.replay.lock().map_err(|_| anyhow!("replay poisoned"))?;
+
+        self.response = Some(replay.next_response(cmd));
+        Ok(())
+    }
+
+    fn read_response(&mut self) -> anyhow::Result<CompactString> {
+        self.response
+            .take()
+            .unwrap_or_else(|| Err(anyhow!("no command was written")))
+    }
+
+    fn protocol(&self) -> Option<Protocol> {
+        self.replay.lock().ok()?.protocol()
+    }
+}
+
+/// Playback positions within a capture
+struct Replay {
+    /// Entries from the capture file
+    entries: Vec<CaptureEntry>,
+    /// Index of the next entry to consider for each command, each command
+    /// has its own position so commands replay independently of each other
+    positions: HashMap<String, usize>,
+    /// Whether to start again from the beginning once the
+    /// end of the capture is reached
+    repeat: bool,
+}
+
+impl Replay {
+    /// Loads a replay from the capture file at the provided `path`
+    fn from_file(path: &Path, repeat: bool) -> anyhow::Result<Self> {
+        let value = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read capture file: {}", path.display()))?;
+        Self::from_str(&value, repeat)
+    }
+
+    /// Loads a replay from the contents of a capture file
+    fn from_str(value: &str, repeat: bool) -> anyhow::Result<Self> {
+        let entries = value
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line)
+                    .with_context(|| format!("invalid capture entry on line {}", index + 1))
+            })
+            .collect::<anyhow::Result<Vec<CaptureEntry>>>()?;
+
+        Ok(Self {
+            entries,
+            positions: HashMap::new(),
+            repeat,
+        })
+    }
+
+    /// Protocol spoken by the recorded device, determined from
+    /// the first recorded state query
+    fn protocol(&self) -> Option<Protocol> {
+        self.entries
+            .iter()
+            .find_map(|entry| Protocol::from_state_command(&entry.command))
+    }
+
+    /// Finds the next recorded response for the provided command
+    fn next_response(&mut self, cmd: &str) -> anyhow::Result<CompactString> {
+        let matches = |entry: &CaptureEntry| entry.command == cmd;
+        let position = self.positions.get(cmd).copied().unwrap_or_default();
+
+        let mut index = self.entries[position..]
+            .iter()
+            .position(matches)
+            .map(|index| position + index);
+
+        if index.is_none() && self.repeat {
+            index = self.entries.iter().position(matches);
+        }
+
+        let index = index.ok_or_else(|| anyhow!("capture has no more responses for {cmd}"))?;
+        self.positions.insert(cmd.to_string(), index + 1);
+
+        self.entries[index].result()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Replay;
+    use crate::{
+        config::SimulatedConfig,
+        ups::{
+            DevicePowerState, DeviceState,
+            command::FromDeviceResponse,
+            device::{Device, DeviceCreator, RecordingDeviceCreator, SimulatedDeviceCreator},
+        },
+    };
+
+    /// Capture with utility power being lost and then restored
+    const OUTAGE_CAPTURE: &str = r#"
+{"timestamp":"2026-10-17T00:00:00Z","command":"QS","response":"(240.0 240.0 240.0 020 50.0 27.0 30.0 00001001"}
+{"timestamp":"2026-10-17T00:00:01Z","command":"QI","response":"(100 02400 50.0 000.5 175 290 0 0000020000112000"}
+{"timestamp":"2026-10-17T00:00:03Z","command":"QS","response":"(000.0 240.0 240.0 020 50.0 26.0 30.0 10001001"}
+{"timestamp":"2026-10-17T00:00:06Z","command":"QS","error":"Failed to read response"}
+{"timestamp":"2026-10-17T00:00:09Z","command":"QS","response":"(240.0 240.0 240.0 020 50.0 26.0 30.0 00001001"}
+"#;
+
+    /// Responses should be replayed in order for each command
+    #[test]
+    fn test_replay_order() {
+        let mut replay = Replay::from_str(OUTAGE_CAPTURE, false).unwrap();
+
+        let state = |response| DeviceState::from_device_response(response).unwrap();
+
+        // Other commands don't move the position of the state query
+        assert!(replay.next_response("QI").is_ok());
+        assert!(replay.next_response("QI").is_err());
+
+        let first = state(replay.next_response("QS").unwrap());
+        assert_eq!(first.device_power_state, DevicePowerState::Utility);
+
+        let second = state(replay.next_response("QS").unwrap());
+        assert_eq!(second.device_power_state, DevicePowerState::Battery);
+
+        // Recorded errors are replayed
+        assert!(replay.next_response("QS").is_err());
+
+        let fourth = state(replay.next_response("QS").unwrap());
+        assert_eq!(fourth.device_power_state, DevicePowerState::Utility);
+
+        // End of the capture is an error
+        assert!(replay.next_response("QS").is_err());
+    }
+
+    /// Repeating replays should start again from the beginning
+    #[test]
+    fn test_replay_repeat() {
+        let mut replay = Replay::from_str(OUTAGE_CAPTURE, true).unwrap();
+
+        for _ in 0..4 {
+            _ = replay.next_response("QS");
+        }
+
+        let response = replay.next_response("QS").unwrap();
+        assert!(response.ends_with("00001001"));
+        assert_eq!(replay.positions.get("QS"), Some(&1));
+    }
+
+    /// Traffic recorded from a device should replay the same responses
+    #[test]
+    fn test_record_replay() {
+        let path =
+            std::env::temp_dir().join(format!("oguard-capture-{}.jsonl", std::process::id()));
+
+        let creator = SimulatedDeviceCreator::new(&SimulatedConfig::default());
+        let creator = RecordingDeviceCreator::new(creator, &path).unwrap();
+        let mut device = creator.try_create_device().unwrap();
+
+        let info = device.send_command("I").unwrap();
+        let nak = device.send_command("UNKNOWN").unwrap();
+
+        let mut replay = Replay::from_file(&path, false).unwrap();
+        _ = std::fs::remove_file(&path);
+
+        assert_eq!(replay.next_response("I").unwrap(), info);
+        assert_eq!(replay.next_response("UNKNOWN").unwrap(), nak);
+    }
+}
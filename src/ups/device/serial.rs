@@ -0,0 +1,179 @@
+//! # Serial Device
+//!
+//! RS-232 / USB-serial transport for UPS devices that speak the same
+//! Megatec style text protocol over a serial line (i.e `/dev/ttyUSB0`)
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    time::Duration,
+};
+
+use anyhow::Context;
+use compact_str::CompactString;
+use serialport::{ClearBuffer, DataBits, Parity, SerialPort, StopBits};
+
+use super::{Device, DeviceCreator};
+use crate::config::{SerialConfig, SerialParity};
+
+/// Size for the serial device read buffer
+const SERIAL_READ_BUFFER_SIZE: usize = 128;
+
+/// Device connected over a serial port
+pub struct SerialDevice {
+    /// The underlying serial port
+    port: Box<dyn SerialPort>,
+    /// Buffer for reading from the device
+    read_buffer: [u8; SERIAL_READ_BUFFER_SIZE],
+    /// Buffer for writing to the device
+    write_buffer: Vec<u8>,
+}
+
+/// Creator for [SerialDevice]s, opens the configured port
+pub struct SerialDeviceCreator {
+    /// Path to the serial port (i.e /dev/ttyUSB0 or COM1)
+    port: String,
+    /// Baud rate for the port
+    baud_rate: u32,
+    /// Parity checking mode
+    parity: Parity,
+    /// Number of bits per character
+    data_bits: DataBits,
+    /// Number of stop bits
+    stop_bits: StopBits,
+    /// Timeout to wait for a response
+    timeout: Duration,
+}
+
+impl SerialDeviceCreator {
+    pub fn new(config: &SerialConfig) -> anyhow::Result<Self> {
+        let parity = match config.parity {
+            SerialParity::None => Parity::None,
+            SerialParity::Odd => Parity::Odd,
+            SerialParity::Even => Parity::Even,
+        };
+
+        let data_bits = match config.data_bits {
+            5 => DataBits::Five,
+            6 => DataBits::Six,
+            7 => DataBits::Seven,
+            8 => DataBits::Eight,
+            value => return Err(anyhow::anyhow!("unsupported serial data bits: {value}")),
+        };
+
+        let stop_bits = match config.stop_bits {
+            1 => StopBits::One,
+            2 => StopBits::Two,
+            value => return Err(anyhow::anyhow!("unsupported serial stop bits: {value}")),
+        };
+
+        Ok(Self {
+            port: config.port.clone(),
+            baud_rate: config.baud_rate,
+            parity,
+            data_bits,
+            stop_bits,
+            timeout: Duration::from_millis(config.timeout_ms),
+        })
+    }
+}
+
+impl DeviceCreator for SerialDeviceCreator {
+    type Output = SerialDevice;
+
+    fn try_create_device(&self) -> anyhow::Result<Self::Output> {
+        let port = serialport::new(&self.port, self.baud_rate)
+            .parity(self.parity)
+            .data_bits(self.data_bits)
+            .stop_bits(self.stop_bits)
+            .timeout(self.timeout)
+            .open()
+            .with_context(|| format!("failed to open serial port {}", self.port))?;
+
+        Ok(SerialDevice {
+            port,
+            read_buffer: [0u8; SERIAL_READ_BUFFER_SIZE],
+            write_buffer: Vec::with_capacity(SERIAL_READ_BUFFER_SIZE),
+        })
+    }
+}
+
+impl Device for SerialDevice {
+    type Creator = SerialDeviceCreator;
+
+    /// Sends a command over the serial port, commands end with a carriage
+    /// return to indicate the end of a command
+    fn write_command(&mut self, cmd: &str) -> anyhow::Result<()> {
+        // Discard any stale bytes from a previous timed out response
+        self.port
+            .clear(ClearBuffer::Input)
+            .context("failed to clear input buffer")?;
+
+        let write_buffer = &mut self.write_buffer;
+
+        write_buffer.extend_from_slice(cmd.as_bytes());
+        write_buffer.push(b'\r');
+
+        let result = self
+            .port
+            .write_all(write_buffer)
+            .and_then(|_| self.port.flush());
+
+        // Reset the write buffer
+        write_buffer.clear();
+
+        result.context("failed to write command")?;
+
+        Ok(())
+    }
+
+    /// Reads a response from the device, terminated by a carriage return
+    /// or the configured timeout
+    fn read_response(&mut self) -> anyhow::Result<CompactString> {
+        let mut out = CompactString::default();
+
+        loop {
+            let count = match self.port.read(&mut self.read_buffer) {
+                Ok(value) => value,
+                // Commands without a response will time out
+                Err(err) if err.kind() == ErrorKind::TimedOut => return Ok(out),
+                Err(err) => return Err(err).context("Failed to read response"),
+            };
+
+            if count == 0 {
+                return Ok(out);
+            }
+
+            let chars = self
+                .read_buffer
+                .iter()
+                // Take only available length
+                .take(count)
+                // Bytes are characters
+                .map(|value| *value as char);
+
+            for char in chars {
+                // Break response at carriage return
+                if char == '\r' {
+                    return Ok(out);
+                }
+
+                out.push(char);
+            }
+        }
+    }
+
+    fn is_disconnect_error(&self, err: &anyhow::Error) -> bool {
+        if let Some(err) = err.downcast_ref::<serialport::Error>() {
+            return matches!(err.kind(), serialport::ErrorKind::NoDevice);
+        }
+
+        err.downcast_ref::<std::io::Error>().is_some_and(|err| {
+            matches!(
+                err.kind(),
+                ErrorKind::BrokenPipe | ErrorKind::NotConnected | ErrorKind::UnexpectedEof
+            ) ||
+            // Unplugged USB-serial adapters report EIO/ENXIO on unix
+            matches!(err.raw_os_error(), Some(5 | 6))
+        })
+    }
+}
@@ -0,0 +1,466 @@
+//! # Simulated Device
+//!
+//! In memory UPS that speaks the same text protocol as a real device, used
+//! for development and demos without a UPS plugged in. The simulation is
+//! steered through a [SharedSimulation] to trigger things like AC loss,
+//! faults and changes in load while battery drain, charging, self tests
+//! and shutdowns play out on their own
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use compact_str::{CompactString, format_compact};
+use serde::Serialize;
+
+use super::{Device, DeviceCreator};
+use crate::{config::SimulatedConfig, ups::protocol::Protocol};
+
+/// Shared reference to a [Simulation]
+pub type SharedSimulation = Arc<Mutex<Simulation>>;
+
+/// Capacity percentage below which the battery is considered low
+const LOW_BATTERY_CAPACITY: f64 = 20.0;
+
+/// Voltage of the simulated utility power
+const UTILITY_VOLTAGE: f64 = 240.0;
+
+/// Frequency of the simulated utility power
+const UTILITY_FREQUENCY: f64 = 50.0;
+
+/// Minutes for the simulated battery to charge from empty
+const FULL_CHARGE_MINUTES: f64 = 60.0;
+
+/// Duration of a quick battery test
+const QUICK_TEST_DURATION: Duration = Duration::from_secs(10);
+
+/// Device that responds using a [Simulation]
+pub struct SimulatedDevice {
+    /// The simulation to respond with
+    simulation: SharedSimulation,
+    /// Response to the last written command
+    response: Option<CompactString>,
+}
+
+/// Creator for [SimulatedDevice]s, all devices share the same simulation
+pub struct SimulatedDeviceCreator {
+    /// The shared simulation
+    simulation: SharedSimulation,
+}
+
+impl SimulatedDeviceCreator {
+    pub fn new(config: &SimulatedConfig) -> Self {
+        Self {
+            simulation: Arc::new(Mutex::new(Simulation::new(config))),
+        }
+    }
+
+    /// Shared simulation used by the created devices
+    pub fn simulation(&self) -> &SharedSimulation {
+        &self.simulation
+    }
+}
+
+impl DeviceCreator for SimulatedDeviceCreator {
+    type Output = SimulatedDevice;
+
+    fn try_create_device(&self) -> anyhow::Result<Self::Output> {
+        Ok(SimulatedDevice {
+            simulation: self.simulation.clone(),
+            response: None,
+        })
+    }
+}
+
+impl Device for SimulatedDevice {
+    type Creator = SimulatedDeviceCreator;
+
+    fn write_command(&mut self, cmd: &str) -> anyhow::Result<()> {
+        let mut simulation = self
+            .simulation
+            .lock()
+            .map_err(|_| anyhow!("simulation poisoned"))?;
+
+        self.response = Some(simulation.handle_command(cmd));
+        Ok(())
+    }
+
+    fn read_response(&mut self) -> anyhow::Result<CompactString> {
+        Ok(self.response.take().unwrap_or_default())
+    }
+
+    fn protocol(&self) -> Option<Protocol> {
+        Some(Protocol::Qs)
+    }
+}
+
+/// Battery test running on the simulation
+#[derive(Debug, Clone, Copy)]
+enum SimulatedTest {
+    /// Test that ends at a specific time
+    Until(Instant),
+    /// Test that ends when the battery is low
+    UntilLow,
+}
+
+/// Shutdown scheduled on the simulation
+#[derive(Debug, Clone, Copy)]
+struct SimulatedShutdown {
+    /// When the output turns off
+    shutdown_at: Instant,
+    /// How long after shutting down the output turns back on,
+    /// [None] to stay off
+    restore_after: Option<Duration>,
+}
+
+/// State of a simulated UPS
+pub struct Simulation {
+    /// Whether utility power is available
+    ac_power: bool,
+    /// Percentage load on the output
+    load_percent: u8,
+    /// Battery capacity percentage
+    capacity: f64,
+    /// Whether the UPS is in a fault state
+    fault: bool,
+    /// Whether the buzzer is enabled
+    buzzer: bool,
+    /// Running battery test
+    self_test: Option<SimulatedTest>,
+    /// Pending or active shutdown
+    shutdown: Option<SimulatedShutdown>,
+    /// Input voltage at the last transfer to battery
+    input_fault_voltage: f64,
+    /// Minutes a full battery lasts at 100% load
+    full_load_runtime_minutes: f64,
+    /// Multiplier for how fast the battery drains and charges
+    time_scale: f64,
+    /// Last time the simulation was updated
+    last_update: Instant,
+}
+
+/// Snapshot of the simulation state
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulationStatus {
+    /// Whether utility power is available
+    pub ac_power: bool,
+    /// Percentage load on the output
+    pub load_percent: u8,
+    /// Battery capacity percentage
+    pub capacity: u8,
+    /// Whether the UPS is in a fault state
+    pub fault: bool,
+    /// Whether the buzzer is enabled
+    pub buzzer: bool,
+    /// Whether a battery test is running
+    pub self_test: bool,
+    /// Whether a shutdown is pending or active
+    pub shutdown: bool,
+    /// Whether the output is turned off
+    pub output_off: bool,
+}
+
+impl Simulation {
+    pub fn new(config: &SimulatedConfig) -> Self {
+        Self {
+            ac_power: true,
+            load_percent: config.load_percent.min(100),
+            capacity: 100.0,
+            fault: false,
+            buzzer: true,
+            self_test: None,
+            shutdown: None,
+            input_fault_voltage: UTILITY_VOLTAGE,
+            full_load_runtime_minutes: config.full_load_runtime_minutes.max(1.0),
+            time_scale: config.time_scale.max(0.0),
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Sets whether utility power is available
+    pub fn set_ac_power(&mut self, ac_power: bool) {
+        self.update();
+
+        if self.ac_power && !ac_power {
+            self.input_fault_voltage = UTILITY_VOLTAGE;
+        }
+
+        self.ac_power = ac_power;
+    }
+
+    /// Sets the percentage load on the output
+    pub fn set_load_percent(&mut self, load_percent: u8) {
+        self.update();
+        self.load_percent = load_percent.min(100);
+    }
+
+    /// Sets the battery capacity percentage
+    pub fn set_capacity(&mut self, capacity: u8) {
+        self.update();
+        self.capacity = capacity.min(100) as f64;
+    }
+
+    /// Sets whether the UPS is in a fault state
+    pub fn set_fault(&mut self, fault: bool) {
+        self.update();
+        self.fault = fault;
+    }
+
+    /// Provides a snapshot of the current simulation state
+    pub fn status(&mut self) -> SimulationStatus {
+        self.update();
+
+        SimulationStatus {
+            ac_power: self.ac_power,
+            load_percent: self.load_percent,
+            capacity: self.capacity.round() as u8,
+            fault: self.fault,
+            buzzer: self.buzzer,
+            self_test: self.self_test.is_some(),
+            shutdown: self.shutdown.is_some(),
+            output_off: self.is_output_off(Instant::now()),
+        }
+    }
+
+    /// Whether the output has been turned off by a shutdown or a depleted battery
+    fn is_output_off(&self, now: Instant) -> bool {
+        let shutdown = self
+            .shutdown
+            .is_some_and(|shutdown| now >= shutdown.shutdown_at);
+        let depleted = !self.ac_power && self.capacity <= 0.0;
+
+        shutdown || depleted
+    }
+
+    /// Whether the output is being powered from the battery
+    fn is_on_battery(&self, now: Instant) -> bool {
+        !self.is_output_off(now) && (!self.ac_power || self.self_test.is_some())
+    }
+
+    /// Remaining battery time in seconds at the current load
+    fn remaining_time(&self) -> u32 {
+        let load = self.load_percent.max(1) as f64;
+        (self.capacity * self.full_load_runtime_minutes * 60.0 / load) as u32
+    }
+
+    /// Advances the simulation to the current time
+    fn update(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_update).as_secs_f64() * self.time_scale;
+        self.last_update = now;
+
+        // Drain the battery while it's in use, charge it when utility power is available
+        if self.is_on_battery(now) {
+            let load = self.load_percent.max(1) as f64;
+            let drain = elapsed * load / (self.full_load_runtime_minutes * 60.0);
+            self.capacity = (self.capacity - drain).max(0.0);
+        } else if self.ac_power {
+            let charge = elapsed * 100.0 / (FULL_CHARGE_MINUTES * 60.0);
+            self.capacity = (self.capacity + charge).min(100.0);
+        }
+
+        // End finished battery tests
+        self.self_test = match self.self_test {
+            Some(SimulatedTest::Until(end)) if now >= end => None,
+            Some(SimulatedTest::UntilLow) if self.capacity < LOW_BATTERY_CAPACITY => None,
+            // Battery tests can't run without utility power
+            Some(_) if !self.ac_power => None,
+            self_test => self_test,
+        };
+
+        // Restore after a completed shutdown once utility power is available
+        if let Some(SimulatedShutdown {
+            shutdown_at,
+            restore_after: Some(restore_after),
+        }) = self.shutdown
+            && self.ac_power
+            && now >= shutdown_at + restore_after
+        {
+            self.shutdown = None;
+        }
+    }
+
+    /// Handles a protocol command providing the response
+    fn handle_command(&mut self, cmd: &str) -> CompactString {
+        self.update();
+
+        let now = Instant::now();
+
+        match cmd {
+            "QS" => self.device_state(now),
+            "QI" => format_compact!(
+                "({:03} {:05} 50.0 000.5 175 290 0 0000020000112000",
+                self.capacity.round() as u8,
+                self.remaining_time()
+            ),
+            "I" => format_compact!(
+                "#{:<15} {:<10} {:<10}",
+                "OGuard",
+                "Simulated",
+                crate::VERSION
+            ),
+            "F" => format_compact!(
+                "#{:05.1} 004 024.0 {:04.1}",
+                UTILITY_VOLTAGE,
+                UTILITY_FREQUENCY
+            ),
+            "Q" => {
+                self.buzzer = !self.buzzer;
+                "(ACK".into()
+            }
+            "T" => self.start_test(SimulatedTest::Until(now + QUICK_TEST_DURATION)),
+            "TL" => self.start_test(SimulatedTest::UntilLow),
+            "CT" => {
+                self.self_test = None;
+                "(ACK".into()
+            }
+            "C" => {
+                self.shutdown = None;
+                "(ACK".into()
+            }
+            cmd => {
+                if let Some(minutes) = cmd
+                    .strip_prefix('T')
+                    .and_then(|minutes| minutes.parse::<u64>().ok())
+                {
+                    let duration = Duration::from_secs(minutes * 60);
+                    return self.start_test(SimulatedTest::Until(now + duration));
+                }
+
+                if let Some(shutdown) = cmd.strip_prefix('S')
+                    && let Some(shutdown) = Self::parse_shutdown(shutdown, now)
+                {
+                    self.shutdown = Some(shutdown);
+                    return "(ACK".into();
+                }
+
+                "(NAK".into()
+            }
+        }
+    }
+
+    /// Creates the QS device state response
+    fn device_state(&self, now: Instant) -> CompactString {
+        let output_off = self.is_output_off(now) || self.fault;
+
+        let input_voltage = if self.ac_power { UTILITY_VOLTAGE } else { 0.0 };
+        let output_voltage = if output_off { 0.0 } else { UTILITY_VOLTAGE };
+        let load_percent = if output_off { 0 } else { self.load_percent };
+        let battery_voltage = 21.0 + 6.0 * self.capacity / 100.0;
+
+        let bit = |value: bool| if value { '1' } else { '0' };
+        let status: CompactString = [
+            bit(!self.ac_power),
+            bit(self.capacity < LOW_BATTERY_CAPACITY),
+            '0',
+            bit(self.fault),
+            // Line interactive device
+            '1',
+            bit(self.self_test.is_some()),
+            bit(self.shutdown.is_some()),
+            bit(self.buzzer),
+        ]
+        .into_iter()
+        .collect();
+
+        format_compact!(
+            "({:05.1} {:05.1} {:05.1} {:03} {:04.1} {:04.1} 30.0 {}",
+            input_voltage,
+            self.input_fault_voltage,
+            output_voltage,
+            load_percent,
+            UTILITY_FREQUENCY,
+            battery_voltage,
+            status
+        )
+    }
+
+    /// Starts a battery test, tests can only run with utility power
+    fn start_test(&mut self, test: SimulatedTest) -> CompactString {
+        if !self.ac_power || self.fault {
+            return "(NAK".into();
+        }
+
+        self.self_test = Some(test);
+        "(ACK".into()
+    }
+
+    /// Parses the delay and restore time from a shutdown command (i.e `.5R0003`)
+    fn parse_shutdown(value: &str, now: Instant) -> Option<SimulatedShutdown> {
+        let (delay, restore) = match value.split_once('R') {
+            Some((delay, restore)) => (delay, Some(restore)),
+            None => (value, None),
+        };
+
+        let delay_minutes: f64 = delay.parse().ok()?;
+        let restore_minutes: u64 = match restore {
+            Some(restore) => restore.parse().ok()?,
+            None => 0,
+        };
+
+        Some(SimulatedShutdown {
+            shutdown_at: now + Duration::from_secs_f64(delay_minutes * 60.0),
+            // Restoring after zero minutes means stay off
+            restore_after: (restore_minutes > 0).then(|| Duration::from_secs(restore_minutes * 60)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Simulation;
+    use crate::{
+        config::SimulatedConfig,
+        ups::{
+            DeviceBattery, DeviceInfo, DevicePowerState, DeviceState, command::FromDeviceResponse,
+        },
+    };
+
+    /// Simulated responses should be parsable by the real response parsers
+    #[test]
+    fn test_simulated_responses() {
+        let mut simulation = Simulation::new(&SimulatedConfig::default());
+
+        let state = DeviceState::from_device_response(simulation.handle_command("QS"))
+            .expect("State should parse successfully");
+        assert_eq!(state.device_power_state, DevicePowerState::Utility);
+        assert!(!state.battery_low);
+
+        let battery = DeviceBattery::from_device_response(simulation.handle_command("QI"))
+            .expect("Battery should parse successfully");
+        assert_eq!(battery.capacity, 100);
+
+        let info = DeviceInfo::from_device_response(simulation.handle_command("I"))
+            .expect("Info should parse successfully");
+        assert_eq!(info.model, "Simulated");
+    }
+
+    /// Steering the simulation should be reflected in the device state
+    #[test]
+    fn test_steer_simulation() {
+        let mut simulation = Simulation::new(&SimulatedConfig::default());
+
+        simulation.set_ac_power(false);
+        simulation.set_capacity(10);
+
+        let state = DeviceState::from_device_response(simulation.handle_command("QS"))
+            .expect("State should parse successfully");
+        assert_eq!(state.device_power_state, DevicePowerState::Battery);
+        assert!(state.battery_low);
+
+        // Battery tests can't start without utility power
+        assert_eq!(simulation.handle_command("T"), "(NAK");
+
+        simulation.set_ac_power(true);
+        assert_eq!(simulation.handle_command("T"), "(ACK");
+
+        let state = DeviceState::from_device_response(simulation.handle_command("QS"))
+            .expect("State should parse successfully");
+        assert!(state.battery_self_test);
+
+        assert_eq!(simulation.handle_command("S.5R0003"), "(ACK");
+        assert!(simulation.status().shutdown);
+    }
+}
@@ -0,0 +1,272 @@
+//! # Diagnostics
+//!
+//! Diagnostics about the communication with a device, updated by the executor
+//! as commands are executed. Responses that failed to parse are kept so firmware
+//! quirks can be told apart from corrupted responses, and statistics are kept for
+//! each command to report on the health of the device connection
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::command::{ParseErrorKind, ResponseParseError};
+
+/// Shared reference to [DeviceDiagnostics]
+pub type SharedDiagnostics = Arc<Mutex<DeviceDiagnostics>>;
+
+/// Number of recent latency samples kept per command for the p95 latency
+const LATENCY_SAMPLES: usize = 100;
+
+/// Diagnostics for a single device
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeviceDiagnostics {
+    /// Responses that failed to parse keyed by the command name
+    pub parse_failures: HashMap<&'static str, ParseFailures>,
+    /// Statistics keyed by the command name
+    #[serde(skip)]
+    pub commands: HashMap<&'static str, CommandStats>,
+    /// Number of times a device has been connected
+    #[serde(skip)]
+    pub connections: u64,
+    /// When the device last responded successfully
+    #[serde(skip)]
+    pub last_success: Option<DateTime<Utc>>,
+}
+
+/// Statistics for a single command
+#[derive(Debug, Clone, Default)]
+pub struct CommandStats {
+    /// Number of times the command was sent to the device
+    pub count: u64,
+    /// Number of times the command failed
+    pub errors: u64,
+    /// Number of times the command was answered without the device
+    pub cache_hits: u64,
+    /// Total round-trip time of all the sent commands
+    pub total_latency: Duration,
+    /// Round-trip times of the most recently sent commands
+    pub latencies: VecDeque<Duration>,
+    /// When the command last succeeded
+    pub last_success: Option<DateTime<Utc>>,
+}
+
+impl CommandStats {
+    /// Average round-trip time of the sent commands
+    fn average_latency(&self) -> Option<Duration> {
+        let count = u32::try_from(self.count).ok().filter(|count| *count > 0)?;
+        Some(self.total_latency / count)
+    }
+
+    /// 95th percentile round-trip time of the recently sent commands
+    fn p95_latency(&self) -> Option<Duration> {
+        let mut latencies: Vec<Duration> = self.latencies.iter().copied().collect();
+        latencies.sort_unstable();
+
+        let index = (latencies.len() * 95).div_ceil(100).checked_sub(1)?;
+        latencies.get(index).copied()
+    }
+
+    /// Portion of the requests for the command that were served from the cache
+    fn cache_hit_rate(&self) -> f64 {
+        let total = self.count + self.cache_hits;
+        if total == 0 {
+            return 0.0;
+        }
+
+        self.cache_hits as f64 / total as f64
+    }
+}
+
+/// Health report for the device connection
+#[derive(Debug, Serialize)]
+pub struct DeviceHealth {
+    /// Whether the device is currently connected
+    pub connected: bool,
+    /// Number of times the device had to be reconnected
+    pub reconnects: u64,
+    /// When the device last responded successfully
+    pub last_success_at: Option<DateTime<Utc>>,
+    /// Health of each command keyed by the command name
+    pub commands: HashMap<&'static str, CommandHealth>,
+}
+
+/// Health report for a single command
+#[derive(Debug, Serialize)]
+pub struct CommandHealth {
+    /// Number of times the command was sent to the device
+    pub count: u64,
+    /// Number of times the command failed
+    pub error_count: u64,
+    /// Number of times the command was answered from the cache
+    pub cache_hits: u64,
+    /// Portion of the requests that were answered from the cache
+    pub cache_hit_rate: f64,
+    /// Average round-trip time in milliseconds
+    pub average_latency_ms: Option<f64>,
+    /// 95th percentile round-trip time in milliseconds
+    pub p95_latency_ms: Option<f64>,
+    /// When the command last succeeded
+    pub last_success_at: Option<DateTime<Utc>>,
+}
+
+/// Parse failures for a single command
+#[derive(Debug, Clone, Serialize)]
+pub struct ParseFailures {
+    /// Number of responses that failed to parse
+    pub count: u64,
+    /// The most recent failure
+    pub last_failure: ParseFailure,
+}
+
+/// Response that failed to parse
+#[derive(Debug, Clone, Serialize)]
+pub struct ParseFailure {
+    /// The raw response from the device
+    pub response: String,
+    /// Name of the field that failed to parse
+    pub field: &'static str,
+    /// Index of the field within the response
+    pub index: usize,
+    /// Why the field failed to parse
+    pub kind: ParseErrorKind,
+    /// When the response was received
+    pub failed_at: DateTime<Utc>,
+}
+
+impl DeviceDiagnostics {
+    /// Records a response to the provided command that failed to parse
+    pub fn record_parse_failure(&mut self, command: &'static str, err: &ResponseParseError) {
+        let failure = ParseFailure {
+            response: err.response.to_string(),
+            field: err.field,
+            index: err.index,
+            kind: err.kind,
+            failed_at: Utc::now(),
+        };
+
+        match self.parse_failures.get_mut(command) {
+            Some(failures) => {
+                failures.count += 1;
+                failures.last_failure = failure;
+            }
+            None => {
+                self.parse_failures.insert(
+                    command,
+                    ParseFailures {
+                        count: 1,
+                        last_failure: failure,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Records the result of sending the provided command to the device
+    pub fn record_execution(&mut self, command: &'static str, latency: Duration, success: bool) {
+        let stats = self.command_stats(command);
+        stats.count += 1;
+        stats.total_latency += latency;
+
+        if stats.latencies.len() == LATENCY_SAMPLES {
+            stats.latencies.pop_front();
+        }
+        stats.latencies.push_back(latency);
+
+        if success {
+            let now = Utc::now();
+            stats.last_success = Some(now);
+            self.last_success = Some(now);
+        } else {
+            stats.errors += 1;
+        }
+    }
+
+    /// Records requests for the provided command that were answered
+    /// without sending the command to the device
+    pub fn record_cache_hits(&mut self, command: &'static str, hits: u64) {
+        if hits == 0 {
+            return;
+        }
+
+        self.command_stats(command).cache_hits += hits;
+    }
+
+    /// Records a device connection
+    pub fn record_connection(&mut self) {
+        self.connections += 1;
+    }
+
+    /// Creates a health report from the current statistics
+    pub fn health(&self, connected: bool) -> DeviceHealth {
+        let commands = self
+            .commands
+            .iter()
+            .map(|(command, stats)| {
+                let health = CommandHealth {
+                    count: stats.count,
+                    error_count: stats.errors,
+                    cache_hits: stats.cache_hits,
+                    cache_hit_rate: stats.cache_hit_rate(),
+                    average_latency_ms: stats.average_latency().map(duration_ms),
+                    p95_latency_ms: stats.p95_latency().map(duration_ms),
+                    last_success_at: stats.last_success,
+                };
+
+                (*command, health)
+            })
+            .collect();
+
+        DeviceHealth {
+            connected,
+            // The first connection is not a reconnect
+            reconnects: self.connections.saturating_sub(1),
+            last_success_at: self.last_success,
+            commands,
+        }
+    }
+
+    fn command_stats(&mut self, command: &'static str) -> &mut CommandStats {
+        self.commands.entry(command).or_default()
+    }
+}
+
+fn duration_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::DeviceDiagnostics;
+
+    /// Statistics should be reported per command
+    #[test]
+    fn test_health() {
+        let mut diagnostics = DeviceDiagnostics::default();
+        diagnostics.record_connection();
+        diagnostics.record_connection();
+
+        for ms in 1..=20 {
+            diagnostics.record_execution("device_state", Duration::from_millis(ms), ms != 20);
+        }
+        diagnostics.record_cache_hits("device_state", 5);
+
+        let health = diagnostics.health(true);
+        assert_eq!(health.reconnects, 1);
+        assert!(health.last_success_at.is_some());
+
+        let qs = health.commands.get("device_state").unwrap();
+        assert_eq!(qs.count, 20);
+        assert_eq!(qs.error_count, 1);
+        assert_eq!(qs.cache_hits, 5);
+        assert_eq!(qs.cache_hit_rate, 0.2);
+        assert_eq!(qs.average_latency_ms, Some(10.5));
+        assert_eq!(qs.p95_latency_ms, Some(19.0));
+    }
+}
@@ -9,16 +9,49 @@
 //! The HID device can only write and read a single request and response at a time so
 //! the device executor will process each request one-by-one ensuring multiple requests
 //! don't corrupt the previous request
+//!
+//! Waiting commands are executed in order of their [CommandPriority] so control commands
+//! don't get stuck behind a burst of queries. Commands that have waited longer than their
+//! deadline are dropped with a [CommandDeadlineExceeded] error and commands where the
+//! caller is no longer waiting for the response are cancelled without being executed
+//!
+//! Query responses are cached in a [ResponseCache], cached responses are sent without
+//! executing the command and identical queries waiting in the queue share the response
+//! from a single execution
+//!
+//! When a device is connected the executor determines the [Protocol] the device speaks,
+//! commands are sent using the wire dialect of that protocol. The first connection is
+//! made on the executor thread so starting an executor never waits on the device
 
 use super::{
-    command::{FromDeviceResponse, IntoDeviceCommand, ResponseCache},
+    command::ResponseParseError,
+    command::{CommandPriority, FromDeviceResponse, IntoDeviceCommand, ResponseCache},
+    control::SharedControlState,
     device::{DefaultDevice, Device, DeviceCreator},
+    diagnostics::SharedDiagnostics,
+    protocol::Protocol,
 };
 use anyhow::{Context, anyhow};
-use hidapi::HidError;
-use log::{error, info, warn};
-use std::time::Duration;
-use tokio::sync::{mpsc, oneshot};
+use log::{debug, info, warn};
+use std::{
+    any::Any,
+    cmp::Ordering,
+    collections::BinaryHeap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering as AtomicOrdering},
+    },
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::sync::{
+    mpsc::{self, error::TryRecvError},
+    oneshot,
+};
+
+/// Number of commands that can be waiting in the channel before
+/// senders have to wait for the executor to queue them
+const COMMAND_CHANNEL_SIZE: usize = 32;
 
 /// Dynamic device command with the response type erased
 type DeviceCommandDyn<D> = Box<dyn DeviceCommandProxy<D>>;
@@ -30,12 +63,60 @@ pub struct DeviceCommand<R: FromDeviceResponse> {
     command: Box<dyn IntoDeviceCommand<Response = R>>,
     /// Channel to send the response to
     tx: Option<oneshot::Sender<anyhow::Result<R>>>,
+    /// Priority of the command
+    priority: CommandPriority,
+    /// Instant the command must be executed before
+    deadline: Instant,
+}
+
+/// Error provided when a command waited longer than its deadline to be executed
+#[derive(Debug, Error)]
+#[error("device command deadline exceeded")]
+pub struct CommandDeadlineExceeded;
+
+/// Error provided when a command could not be executed because the
+/// executor is not connected to the device
+#[derive(Debug, Error)]
+#[error("device is not connected")]
+pub struct DeviceNotConnected;
+
+/// Command waiting in the executor queue
+struct QueuedCommand<D: Device> {
+    /// The waiting command
+    command: DeviceCommandDyn<D>,
+    /// Order the command was received in, commands with the same
+    /// priority are executed in the order they were received
+    sequence: u64,
+}
+
+impl<D: Device> PartialEq for QueuedCommand<D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<D: Device> Eq for QueuedCommand<D> {}
+
+impl<D: Device> PartialOrd for QueuedCommand<D> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<D: Device> Ord for QueuedCommand<D> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.command
+            .priority()
+            .cmp(&other.command.priority())
+            // Earlier commands come first in the max heap
+            .then_with(|| other.sequence.cmp(&self.sequence))
+    }
 }
 
 /// Executor that can run commands on a device
 pub struct DeviceExecutor<D: Device = DefaultDevice> {
-    /// Device to execute commands on
-    device: D,
+    /// Device to execute commands on, [None] while disconnected
+    device: Option<D>,
 
     /// Device creator to make new device connections if
     /// the existing connection fails
@@ -44,84 +125,291 @@ pub struct DeviceExecutor<D: Device = DefaultDevice> {
     /// Cache for responses
     cache: ResponseCache,
 
+    /// State updated by control commands
+    control_state: SharedControlState,
+
+    /// Diagnostics about the communication with the device
+    diagnostics: SharedDiagnostics,
+
+    /// Whether the executor currently has a device
+    connected: Arc<AtomicBool>,
+
+    /// Whether the first attempt at connecting to the device is still running
+    connecting: Arc<AtomicBool>,
+
+    /// Protocol spoken by the current device
+    protocol: Protocol,
+
+    /// Protocol from the configuration, devices are not
+    /// probed when the protocol is configured
+    configured_protocol: Option<Protocol>,
+
     /// Channel to receive commands to execute
     rx: mpsc::Receiver<DeviceCommandDyn<D>>,
+
+    /// Commands waiting to be executed
+    queue: BinaryHeap<QueuedCommand<D>>,
+
+    /// Sequence number for the next queued command
+    sequence: u64,
 }
 
 impl<D: Device> DeviceExecutor<D> {
-    /// Duration to wait before attempting to retry creating a device
-    const RETRY_CREATE_DURATION: Duration = Duration::from_secs(5);
+    /// Delay before the first attempt at recreating a lost device
+    const RETRY_INITIAL_DELAY: Duration = Duration::from_secs(1);
 
-    pub fn start(device_creator: D::Creator) -> anyhow::Result<DeviceExecutorHandle<D>> {
-        // Channel for receiving commands
-        let (tx, rx) = mpsc::channel(8);
+    /// Longest delay between attempts at recreating a lost device
+    const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+    /// Interval to check for commands while waiting to retry creating a device
+    const DISCONNECTED_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    pub fn start(device_creator: D::Creator) -> DeviceExecutorHandle<D> {
+        Self::start_with_options(device_creator, ResponseCache::default(), None)
+    }
 
-        let cache = ResponseCache::default();
+    /// Starts an executor using the provided response cache and protocol, the
+    /// protocol is detected by probing the device when [None]. The handle is
+    /// provided before the device is connected, if the device cannot be created
+    /// the executor will keep trying to create the device in the background
+    pub fn start_with_options(
+        device_creator: D::Creator,
+        cache: ResponseCache,
+        protocol: Option<Protocol>,
+    ) -> DeviceExecutorHandle<D> {
+        // Channel for receiving commands
+        let (tx, rx) = mpsc::channel(COMMAND_CHANNEL_SIZE);
 
-        let device = Self::try_create_device(&device_creator).context("create device")?;
+        let control_state = SharedControlState::default();
+        let diagnostics = SharedDiagnostics::default();
+        let connected = Arc::new(AtomicBool::new(false));
+        let connecting = Arc::new(AtomicBool::new(true));
 
         let executor = DeviceExecutor {
-            device,
+            device: None,
             device_creator,
             rx,
             cache,
+            control_state: control_state.clone(),
+            diagnostics: diagnostics.clone(),
+            connected: connected.clone(),
+            connecting: connecting.clone(),
+            protocol: protocol.unwrap_or_default(),
+            configured_protocol: protocol,
+            queue: BinaryHeap::new(),
+            sequence: 0,
         };
 
         std::thread::spawn(move || executor.process());
 
-        Ok(DeviceExecutorHandle { tx })
+        DeviceExecutorHandle {
+            tx,
+            control_state,
+            diagnostics,
+            connected,
+            connecting,
+        }
     }
 
-    /// Attempts to connect to the HID device
-    fn try_create_device(device_creator: &D::Creator) -> anyhow::Result<D> {
-        let max_attempts = 5;
-        let mut attempt = 0;
+    fn process(mut self) {
+        // Commands sent while connecting wait in the channel until the attempt finishes
+        if let Err(err) = self.connect() {
+            warn!("failed to create device handle, starting disconnected: {err:#}");
+        }
+        self.connecting.store(false, AtomicOrdering::SeqCst);
 
-        let mut last_error: Option<anyhow::Error> = None;
+        while let Some(msg) = self.next_command() {
+            self.execute(msg);
+        }
+    }
 
-        while attempt < max_attempts {
-            match device_creator.try_create_device() {
-                Ok(device) => return Ok(device),
+    /// Moves any commands waiting in the channel into the queue and provides
+    /// the highest priority command, waits for the next command when the
+    /// queue is empty. Provides [None] when the channel is closed
+    fn next_command(&mut self) -> Option<DeviceCommandDyn<D>> {
+        // Recreate the device before executing anything else
+        if self.device.is_none() && !self.reconnect() {
+            return None;
+        }
+
+        while let Ok(msg) = self.rx.try_recv() {
+            self.enqueue(msg);
+        }
+
+        match self.queue.pop() {
+            Some(queued) => Some(queued.command),
+            None => self.rx.blocking_recv(),
+        }
+    }
+
+    /// Attempts to create the device, retrying forever with an increasing delay
+    /// between attempts. Commands received while disconnected are responded to
+    /// with a [DeviceNotConnected] error.
+    ///
+    /// Provides false if the channel was closed while reconnecting
+    fn reconnect(&mut self) -> bool {
+        let mut delay = Self::RETRY_INITIAL_DELAY;
+
+        loop {
+            if !self.fail_waiting_commands() {
+                return false;
+            }
+
+            match self.connect() {
+                Ok(()) => {
+                    info!("acquired new device handle, resuming");
+                    return true;
+                }
                 Err(err) => {
                     warn!(
-                        "failed to create device handle, retrying in {} seconds: {}",
-                        Self::RETRY_CREATE_DURATION.as_secs(),
-                        err
+                        "failed to create device handle, retrying in {} seconds: {err:#}",
+                        delay.as_secs()
                     );
-                    last_error = Some(err);
                 }
             }
 
-            std::thread::sleep(Self::RETRY_CREATE_DURATION);
+            let retry_at = Instant::now() + delay;
+
+            while let Some(remaining) = retry_at.checked_duration_since(Instant::now()) {
+                if !self.fail_waiting_commands() {
+                    return false;
+                }
+
+                std::thread::sleep(remaining.min(Self::DISCONNECTED_POLL_INTERVAL));
+            }
+
+            delay = (delay * 2).min(Self::RETRY_MAX_DELAY);
+        }
+    }
+
+    /// Responds to all the waiting commands with a [DeviceNotConnected] error,
+    /// provides false if the channel is closed
+    fn fail_waiting_commands(&mut self) -> bool {
+        for mut queued in std::mem::take(&mut self.queue) {
+            queued.command.fail(DeviceNotConnected.into());
+        }
+
+        loop {
+            match self.rx.try_recv() {
+                Ok(mut msg) => msg.fail(DeviceNotConnected.into()),
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+
+    /// Creates a new device and determines the protocol it speaks
+    fn connect(&mut self) -> anyhow::Result<()> {
+        let mut device = self.device_creator.try_create_device()?;
+
+        self.protocol = match self.configured_protocol.or_else(|| device.protocol()) {
+            Some(protocol) => protocol,
+            None => match Protocol::probe(&mut device)? {
+                Some(protocol) => {
+                    info!("detected device protocol {protocol}");
+                    protocol
+                }
+                None => {
+                    let protocol = Protocol::default();
+                    warn!("device did not answer any known protocol, using {protocol}");
+                    protocol
+                }
+            },
+        };
 
-            attempt += 1;
+        if let Ok(diagnostics) = &mut self.diagnostics.lock() {
+            diagnostics.record_connection();
         }
 
-        let last_error = last_error.expect("missing last error");
+        // Responses from a previous device may not match the new device
+        self.cache.clear();
+        self.set_device(Some(device));
+        Ok(())
+    }
+
+    /// Replaces the current device updating the connected state
+    fn set_device(&mut self, device: Option<D>) {
+        self.connected
+            .store(device.is_some(), AtomicOrdering::SeqCst);
+        self.device = device;
+    }
 
-        Err(anyhow!("failed to acquire device: {}", last_error))
+    /// Adds a command to the queue
+    fn enqueue(&mut self, command: DeviceCommandDyn<D>) {
+        let sequence = self.sequence;
+        self.sequence = self.sequence.wrapping_add(1);
+        self.queue.push(QueuedCommand { command, sequence });
     }
 
-    fn process(mut self) {
-        while let Some(mut msg) = self.rx.blocking_recv() {
-            while let HandleOutcome::Disconnected = msg.handle(&mut self.device, &mut self.cache) {
-                // Handle is closed, try and create a new one
-                warn!("lost device handle, attempting to create a new one");
-
-                let device = match Self::try_create_device(&self.device_creator) {
-                    Ok(device) => device,
-                    Err(err) => {
-                        error!("failed to acquire new handle: {err}");
-                        return;
-                    }
-                };
-
-                info!("acquired new device handle, resuming");
-
-                // Swap device handle
-                self.device = device;
+    /// Takes the commands waiting in the queue that are identical to the
+    /// command with the provided cache key
+    fn take_identical(&mut self, cache_key: u64) -> Vec<DeviceCommandDyn<D>> {
+        let (identical, remaining): (Vec<_>, Vec<_>) = std::mem::take(&mut self.queue)
+            .into_vec()
+            .into_iter()
+            .partition(|queued| queued.command.cache_key() == Some(cache_key));
+
+        self.queue = BinaryHeap::from(remaining);
+
+        identical.into_iter().map(|queued| queued.command).collect()
+    }
+
+    /// Executes a command, if the device is lost a single attempt is made
+    /// to recreate the device before the command fails
+    fn execute(&mut self, mut msg: DeviceCommandDyn<D>) {
+        // Caller is no longer waiting for the response
+        if msg.is_cancelled() {
+            debug!("skipping cancelled device command");
+            return;
+        }
+
+        if msg.is_expired(Instant::now()) {
+            warn!("device command exceeded its deadline before it could be executed");
+            msg.fail(CommandDeadlineExceeded.into());
+            return;
+        }
+
+        // Identical queries share the response from a single execution
+        let mut identical = match msg.cache_key() {
+            Some(cache_key) => self.take_identical(cache_key),
+            None => Vec::new(),
+        };
+
+        let mut attempted_reconnect = false;
+
+        while let Some(device) = self.device.as_mut() {
+            let outcome = msg.handle(
+                device,
+                self.protocol,
+                &mut self.cache,
+                &self.control_state,
+                &self.diagnostics,
+                &mut identical,
+            );
+
+            if let HandleOutcome::Continue = outcome {
+                return;
+            }
+
+            // Handle is closed, try and create a new one
+            warn!("lost device handle, attempting to create a new one");
+            self.set_device(None);
+
+            if attempted_reconnect {
+                break;
+            }
+
+            attempted_reconnect = true;
+
+            match self.connect() {
+                Ok(()) => info!("acquired new device handle, resuming"),
+                Err(err) => warn!("failed to create device handle: {err:#}"),
             }
         }
+
+        for msg in std::iter::once(&mut msg).chain(identical.iter_mut()) {
+            msg.fail(DeviceNotConnected.into());
+        }
     }
 }
 
@@ -129,12 +417,24 @@ impl<D: Device> DeviceExecutor<D> {
 pub struct DeviceExecutorHandle<D: Device = DefaultDevice> {
     /// Channel to send commands
     tx: mpsc::Sender<DeviceCommandDyn<D>>,
+    /// State updated by control commands
+    control_state: SharedControlState,
+    /// Diagnostics about the communication with the device
+    diagnostics: SharedDiagnostics,
+    /// Whether the executor currently has a device
+    connected: Arc<AtomicBool>,
+    /// Whether the first attempt at connecting to the device is still running
+    connecting: Arc<AtomicBool>,
 }
 
 impl<D: Device> Clone for DeviceExecutorHandle<D> {
     fn clone(&self) -> Self {
         Self {
             tx: self.tx.clone(),
+            control_state: self.control_state.clone(),
+            diagnostics: self.diagnostics.clone(),
+            connected: self.connected.clone(),
+            connecting: self.connecting.clone(),
         }
     }
 }
@@ -145,6 +445,27 @@ impl<D: Device> DeviceExecutorHandle<D> {
         !self.tx.is_closed()
     }
 
+    /// Checks whether the executor is currently connected to the device
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(AtomicOrdering::SeqCst)
+    }
+
+    /// Checks whether the executor is still making its first attempt at
+    /// connecting to the device
+    pub fn is_connecting(&self) -> bool {
+        self.connecting.load(AtomicOrdering::SeqCst)
+    }
+
+    /// State updated by the control commands sent to the device
+    pub fn control_state(&self) -> &SharedControlState {
+        &self.control_state
+    }
+
+    /// Diagnostics about the communication with the device
+    pub fn diagnostics(&self) -> &SharedDiagnostics {
+        &self.diagnostics
+    }
+
     /// Sends a command to the device and receives the response
     pub async fn send<C>(&self, command: C) -> anyhow::Result<C::Response>
     where
@@ -153,6 +474,8 @@ impl<D: Device> DeviceExecutorHandle<D> {
         let (tx, rx) = oneshot::channel();
 
         let msg = DeviceCommand {
+            priority: command.priority(),
+            deadline: Instant::now() + command.deadline(),
             command: Box::new(command),
             tx: Some(tx),
         };
@@ -170,8 +493,35 @@ impl<D: Device> DeviceExecutorHandle<D> {
 /// Type erased proxy over [DeviceCommand] to allow them to
 /// be handled and send their response in a dynamic context
 trait DeviceCommandProxy<D: Device>: Send + 'static {
-    /// Handle the request
-    fn handle(&mut self, device: &mut D, cache: &mut ResponseCache) -> HandleOutcome;
+    /// Handle the request, the `identical` commands are responded to
+    /// with the same response
+    fn handle(
+        &mut self,
+        device: &mut D,
+        protocol: Protocol,
+        cache: &mut ResponseCache,
+        control_state: &SharedControlState,
+        diagnostics: &SharedDiagnostics,
+        identical: &mut [DeviceCommandDyn<D>],
+    ) -> HandleOutcome;
+
+    /// Cache key for the command response
+    fn cache_key(&self) -> Option<u64>;
+
+    /// Responds to the command with the result from an identical command
+    fn respond_identical(&mut self, result: Result<&dyn Any, &anyhow::Error>);
+
+    /// Priority of the command
+    fn priority(&self) -> CommandPriority;
+
+    /// Checks if the caller is no longer waiting for the response
+    fn is_cancelled(&self) -> bool;
+
+    /// Checks if the command deadline has passed
+    fn is_expired(&self, now: Instant) -> bool;
+
+    /// Responds to the command with the provided error without executing it
+    fn fail(&mut self, err: anyhow::Error);
 }
 
 enum HandleOutcome {
@@ -182,36 +532,128 @@ enum HandleOutcome {
 }
 
 impl<D: Device, R: FromDeviceResponse> DeviceCommandProxy<D> for DeviceCommand<R> {
-    fn handle(&mut self, device: &mut D, cache: &mut ResponseCache) -> HandleOutcome {
+    fn priority(&self) -> CommandPriority {
+        self.priority
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.tx.as_ref().is_none_or(|tx| tx.is_closed())
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        now >= self.deadline
+    }
+
+    fn fail(&mut self, err: anyhow::Error) {
+        if let Some(tx) = self.tx.take() {
+            _ = tx.send(Err(err));
+        }
+    }
+
+    fn cache_key(&self) -> Option<u64> {
+        self.command.cache_key()
+    }
+
+    fn respond_identical(&mut self, result: Result<&dyn Any, &anyhow::Error>) {
+        let Some(tx) = self.tx.take() else {
+            return;
+        };
+
+        let result = match result {
+            Ok(value) => value
+                .downcast_ref::<R>()
+                .cloned()
+                .context("mismatched identical command response"),
+            Err(err) => Err(anyhow!("{err:#}")),
+        };
+
+        _ = tx.send(result);
+    }
+
+    fn handle(
+        &mut self,
+        device: &mut D,
+        protocol: Protocol,
+        cache: &mut ResponseCache,
+        control_state: &SharedControlState,
+        diagnostics: &SharedDiagnostics,
+        identical: &mut [DeviceCommandDyn<D>],
+    ) -> HandleOutcome {
+        let command = self.command.get_protocol_command(protocol);
+
         let cache_key = self.command.cache_key();
-        if let Some(cached_response) = cache.get(cache_key) {
-            // Send the cached response
+        if let Some(cached_response) = cache.get::<R>(cache_key) {
+            if let Ok(diagnostics) = &mut diagnostics.lock() {
+                diagnostics.record_cache_hits(self.command.name(), 1 + identical.len() as u64);
+            }
+
+            for other in identical.iter_mut() {
+                other.respond_identical(Ok(&cached_response));
+            }
+
+            // Send the cached response without executing the command
             if let Some(tx) = self.tx.take() {
                 _ = tx.send(Ok(cached_response));
             }
+
+            return HandleOutcome::Continue;
         }
 
-        // Get and execute the command
-        let command = self.command.get_command();
+        // Execute the command
+        let started = Instant::now();
         let result = device
             // Send the command
             .send_command(&command)
             // Attempt to parse the response
-            .and_then(R::from_device_response);
+            .and_then(|response| self.command.parse_response(protocol, response));
+        let latency = started.elapsed();
+
+        if let Ok(diagnostics) = &mut diagnostics.lock() {
+            diagnostics.record_execution(self.command.name(), latency, result.is_ok());
+
+            // Identical commands share the response without being executed
+            diagnostics.record_cache_hits(self.command.name(), identical.len() as u64);
+
+            // Keep track of responses that failed to parse
+            if let Err(err) = result.as_ref()
+                && let Some(err) = err.downcast_ref::<ResponseParseError>()
+            {
+                diagnostics.record_parse_failure(self.command.name(), err);
+            }
+        }
 
-        if is_disconnect_error(result.as_ref()) {
+        if let Err(err) = result.as_ref()
+            && device.is_disconnect_error(err)
+        {
             return HandleOutcome::Disconnected;
         }
 
         // Store successful responses
         if let Some(cache_key) = cache_key
-            && let Ok(value) = result.as_ref() {
-                cache.insert(cache_key, value);
-            }
+            && let Ok(value) = result.as_ref()
+        {
+            let duration = cache.duration(
+                &command,
+                &self.command.get_command(),
+                self.command.cache_duration(),
+            );
+            cache.insert(cache_key, value, duration);
+        }
 
         // Invalidate cache keys
         self.command.invalidate_cache(cache);
 
+        // Record successful control commands
+        if result.is_ok()
+            && let Ok(control_state) = &mut control_state.lock()
+        {
+            self.command.update_control_state(control_state);
+        }
+
+        for other in identical.iter_mut() {
+            other.respond_identical(result.as_ref().map(|value| value as &dyn Any));
+        }
+
         // Send the response
         if let Some(tx) = self.tx.take() {
             _ = tx.send(result);
@@ -221,12 +663,282 @@ impl<D: Device, R: FromDeviceResponse> DeviceCommandProxy<D> for DeviceCommand<R
     }
 }
 
-/// Checks if the provided result contains a disconnect error
-fn is_disconnect_error<T>(result: Result<&T, &anyhow::Error>) -> bool {
-    result
-        .err()
-        // Try downcast to a HID error
-        .and_then(|err| err.downcast_ref::<HidError>())
-        // Check if the error contains the disconnected message
-        .is_some_and(|value| value.to_string().contains("The device is not connected"))
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::BinaryHeap,
+        time::{Duration, Instant},
+    };
+
+    use tokio::sync::oneshot;
+
+    use super::{
+        CommandDeadlineExceeded, DeviceCommand, DeviceCommandDyn, DeviceExecutor,
+        DeviceNotConnected, QueuedCommand,
+    };
+    use crate::{
+        config::SerialConfig,
+        ups::{
+            DeviceBattery, MockDevice, MockDeviceCreator, Protocol, QueryDeviceBattery,
+            QueryDeviceState, RawCommand, ToggleBuzzer,
+            command::{CommandPriority, IntoDeviceCommand, ResponseCache},
+            control::SharedControlState,
+            device::{AnyDevice, AnyDeviceCreator, DeviceCreator, SerialDeviceCreator},
+            diagnostics::SharedDiagnostics,
+        },
+    };
+
+    /// Creates a command for the mock device along with the receiver for its response
+    fn create_command<C: IntoDeviceCommand>(
+        command: C,
+        deadline: Instant,
+    ) -> (
+        DeviceCommandDyn<MockDevice>,
+        oneshot::Receiver<anyhow::Result<C::Response>>,
+    ) {
+        let (tx, rx) = oneshot::channel();
+        let command = DeviceCommand {
+            priority: command.priority(),
+            deadline,
+            command: Box::new(command),
+            tx: Some(tx),
+        };
+
+        (Box::new(command), rx)
+    }
+
+    /// Control commands should be executed before queries, commands with
+    /// the same priority should be executed in the order they were received
+    #[test]
+    fn test_queue_priority() {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let (first, _first_rx) = create_command(QueryDeviceBattery, deadline);
+        let (second, _second_rx) = create_command(QueryDeviceBattery, deadline);
+        let (control, _control_rx) = create_command(ToggleBuzzer, deadline);
+
+        let mut queue = BinaryHeap::new();
+        for (sequence, command) in [first, second, control].into_iter().enumerate() {
+            queue.push(QueuedCommand {
+                command,
+                sequence: sequence as u64,
+            });
+        }
+
+        let order: Vec<(CommandPriority, u64)> = std::iter::from_fn(|| queue.pop())
+            .map(|queued| (queued.command.priority(), queued.sequence))
+            .collect();
+
+        assert_eq!(
+            order,
+            [
+                (CommandPriority::Control, 2),
+                (CommandPriority::Query, 0),
+                (CommandPriority::Query, 1)
+            ]
+        );
+    }
+
+    /// Commands should be cancelled once the caller stops waiting
+    #[test]
+    fn test_cancelled_command() {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let (command, rx) = create_command(QueryDeviceBattery, deadline);
+
+        assert!(!command.is_cancelled());
+        drop(rx);
+        assert!(command.is_cancelled());
+    }
+
+    /// Expired commands should respond with a deadline error
+    #[test]
+    fn test_expired_command() {
+        let deadline = Instant::now();
+        let (mut command, mut rx) = create_command(QueryDeviceBattery, deadline);
+
+        assert!(command.is_expired(Instant::now()));
+        command.fail(CommandDeadlineExceeded.into());
+
+        let err = rx.try_recv().unwrap().unwrap_err();
+        assert!(err.is::<CommandDeadlineExceeded>());
+    }
+
+    /// Cached responses should be sent without executing the command
+    #[test]
+    fn test_cache_hit_skips_device() {
+        let (creator, mut handle) = MockDeviceCreator::new();
+        let mut device = creator.try_create_device().unwrap();
+
+        let cached = DeviceBattery {
+            capacity: 50,
+            remaining_time: 100,
+        };
+
+        let mut cache = ResponseCache::default();
+        cache.insert(0, &cached, Duration::from_secs(10));
+
+        // Response for the device if the command was executed
+        handle.next_response("(100 00200".into());
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let (mut command, mut rx) = create_command(QueryDeviceBattery, deadline);
+        command.handle(
+            &mut device,
+            Protocol::Qs,
+            &mut cache,
+            &SharedControlState::default(),
+            &SharedDiagnostics::default(),
+            &mut [],
+        );
+
+        assert_eq!(rx.try_recv().unwrap().unwrap(), cached);
+        assert!(handle.try_next_command().is_none());
+    }
+
+    /// Identical commands should share the response from a single execution
+    #[test]
+    fn test_identical_commands() {
+        let (creator, mut handle) = MockDeviceCreator::new();
+        let mut device = creator.try_create_device().unwrap();
+        let mut cache = ResponseCache::default();
+
+        handle.next_response("(100 00200".into());
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let (mut command, mut rx) = create_command(QueryDeviceBattery, deadline);
+        let (first, mut first_rx) = create_command(QueryDeviceBattery, deadline);
+        let (second, mut second_rx) = create_command(QueryDeviceBattery, deadline);
+
+        command.handle(
+            &mut device,
+            Protocol::Qs,
+            &mut cache,
+            &SharedControlState::default(),
+            &SharedDiagnostics::default(),
+            &mut [first, second],
+        );
+
+        let expected = DeviceBattery {
+            capacity: 100,
+            remaining_time: 200,
+        };
+
+        assert_eq!(rx.try_recv().unwrap().unwrap(), expected);
+        assert_eq!(first_rx.try_recv().unwrap().unwrap(), expected);
+        assert_eq!(second_rx.try_recv().unwrap().unwrap(), expected);
+
+        // Only a single command should have been executed
+        assert_eq!(handle.try_next_command().as_deref(), Some("QI"));
+        assert!(handle.try_next_command().is_none());
+    }
+
+    /// Executors should start disconnected when the device is unavailable
+    /// and fail commands until the device can be created
+    #[tokio::test]
+    async fn test_start_disconnected() {
+        let config = SerialConfig {
+            port: "/dev/oguard-missing-port".to_string(),
+            ..Default::default()
+        };
+        let creator = AnyDeviceCreator::Serial(SerialDeviceCreator::new(&config).unwrap());
+
+        let executor = DeviceExecutor::<AnyDevice>::start(creator);
+        assert!(!executor.is_connected());
+
+        let err = executor.send(QueryDeviceBattery).await.unwrap_err();
+        assert!(err.is::<DeviceNotConnected>());
+    }
+
+    /// Diagnostics should group commands by name so commands with
+    /// different arguments share a single entry
+    #[tokio::test]
+    async fn test_diagnostics_command_name() {
+        let (creator, handle) = MockDeviceCreator::new();
+        let executor = DeviceExecutor::<MockDevice>::start(creator);
+
+        for command in ["QS", "S.5R0003"] {
+            handle.next_response("(ACK".into());
+            executor
+                .send(RawCommand {
+                    command: command.into(),
+                })
+                .await
+                .unwrap();
+        }
+
+        let diagnostics = executor.diagnostics().lock().unwrap();
+        let health = diagnostics.health(true);
+
+        assert_eq!(health.commands.len(), 1);
+        assert_eq!(health.commands.get("raw").unwrap().count, 2);
+    }
+
+    /// Valid response to the Q1 state query
+    const Q1_STATE: &str = "(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000";
+
+    /// Devices that don't report their protocol should be probed when
+    /// connected and queried using the protocol they answered, Q1 devices
+    /// have their battery estimated from the state query
+    #[tokio::test]
+    async fn test_connect_probe() {
+        let (creator, mut handle) = MockDeviceCreator::new();
+        let creator = creator.with_protocol(None);
+
+        // Starting should not wait for the probe to be answered
+        let executor = DeviceExecutor::<MockDevice>::start(creator);
+        assert!(executor.is_connecting());
+        assert!(!executor.is_connected());
+
+        assert_eq!(handle.next_command().await.as_deref(), Some("QS"));
+        handle.next_response("(NAK".into());
+        assert_eq!(handle.next_command().await.as_deref(), Some("Q1"));
+        handle.next_response(Q1_STATE.into());
+
+        let state_executor = executor.clone();
+        let request = tokio::spawn(async move { state_executor.send(QueryDeviceState).await });
+
+        assert_eq!(handle.next_command().await.as_deref(), Some("Q1"));
+        handle.next_response(Q1_STATE.into());
+
+        let state = request.await.unwrap().unwrap();
+        assert!(state.fault_mode);
+
+        let request = tokio::spawn(async move { executor.send(QueryDeviceBattery).await });
+
+        assert_eq!(handle.next_command().await.as_deref(), Some("Q1"));
+        handle.next_response(Q1_STATE.into());
+
+        let battery = request.await.unwrap().unwrap();
+        assert_eq!(battery.capacity, 73);
+        assert_eq!(battery.remaining_time, 0);
+    }
+
+    /// Devices that don't answer any of the protocols should
+    /// fall back to the default protocol
+    #[tokio::test]
+    async fn test_connect_probe_fallback() {
+        let (creator, mut handle) = MockDeviceCreator::new();
+        let creator = creator.with_protocol(None);
+
+        let executor = DeviceExecutor::<MockDevice>::start(creator);
+
+        for protocol in Protocol::ALL {
+            let command = handle.next_command().await;
+            assert_eq!(command.as_deref(), Some(protocol.state_command()));
+            handle.next_response("(NAK".into());
+        }
+
+        let state_executor = executor.clone();
+        let request = tokio::spawn(async move { state_executor.send(QueryDeviceState).await });
+
+        let command = handle.next_command().await;
+        assert_eq!(
+            command.as_deref(),
+            Some(Protocol::default().state_command())
+        );
+        handle.next_response("(237.1 237.1 237.1 008 50.1 27.1 --.- 00001001".into());
+
+        request.await.unwrap().unwrap();
+        assert!(executor.is_connected());
+        assert!(!executor.is_connecting());
+    }
 }
@@ -5,14 +5,23 @@
 
 pub mod command;
 pub mod commands;
+pub mod control;
 pub mod device;
+pub mod diagnostics;
 pub mod executor;
 pub mod models;
+pub mod protocol;
+pub mod registry;
 
 pub use commands::*;
-pub use device::HidDeviceCreator;
+pub use device::{
+    AnyDeviceCreator, HidDeviceCreator, SerialDeviceCreator, SharedSimulation,
+    SimulatedDeviceCreator,
+};
 pub use executor::{DeviceExecutor, DeviceExecutorHandle};
 pub use models::*;
+pub use protocol::Protocol;
+pub use registry::{DeviceId, DeviceRegistry, RegisteredDevice, SharedDeviceRegistry};
 
 #[cfg(test)]
 pub use device::test::*;
@@ -1,5 +1,7 @@
 //! Models used for parsing command responses
 
+use chrono::{DateTime, TimeDelta, Utc};
+use garde::Validate;
 use ordered_float::OrderedFloat;
 use sea_orm::FromJsonQueryResult;
 use serde::{Deserialize, Serialize};
@@ -9,6 +11,10 @@ use serde::{Deserialize, Serialize};
 pub struct DeviceState {
     /// Voltage going into the UPS (Power coming from wall)
     pub input_voltage: OrderedFloat<f64>,
+    /// Input voltage at the time of the last transfer to battery
+    /// (Not available on all devices)
+    #[serde(default)]
+    pub input_fault_voltage: Option<OrderedFloat<f64>>,
     /// Voltage coming out of the UPS (Power coming from UPS)
     pub output_voltage: OrderedFloat<f64>,
     /// Percentage load/usage of the UPS
@@ -17,16 +23,26 @@ pub struct DeviceState {
     pub output_frequency: OrderedFloat<f64>,
     /// Voltage of the battery
     pub battery_voltage: OrderedFloat<f64>,
+    /// Internal temperature of the UPS in degrees celsius
+    /// (Not available on all devices)
+    #[serde(default)]
+    pub temperature: Option<OrderedFloat<f64>>,
     /// Current source of power
     pub device_power_state: DevicePowerState,
     /// Low battery state
     pub battery_low: bool,
+    /// Automatic voltage regulation (bypass/boost) is active
+    #[serde(default)]
+    pub bypass_boost_active: bool,
     /// Fault state
     pub fault_mode: bool,
     /// Device line type
     pub device_line_type: DeviceLineType,
     /// Device self test state
     pub battery_self_test: bool,
+    /// UPS shutdown is active
+    #[serde(default)]
+    pub shutdown_active: bool,
     /// Buzzer controller state
     pub buzzer_control: bool,
 }
@@ -89,7 +105,7 @@ pub enum DeviceLineType {
 }
 
 /// Current source of power for the UPS
-#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, FromJsonQueryResult)]
 pub enum DevicePowerState {
     /// Device is being powered from a socket, battery is not used
     Utility,
@@ -105,3 +121,100 @@ pub struct DeviceBattery {
     /// Remaining time of the battery charge in seconds
     pub remaining_time: u32,
 }
+
+/// Snapshot of the device at the time of an event
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, FromJsonQueryResult)]
+pub struct DeviceSnapshot {
+    /// State of the device that triggered the event
+    pub state: DeviceState,
+    /// Battery of the device, [None] if the battery could not be queried
+    pub battery: Option<DeviceBattery>,
+}
+
+/// Response from a device info query, identifies the device
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// Name of the company that made the device
+    pub company: String,
+    /// Model of the device
+    pub model: String,
+    /// Firmware version of the device
+    pub version: String,
+}
+
+/// Response from a device rating query, the rated values of the device
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DeviceRating {
+    /// Rated output voltage
+    pub voltage: OrderedFloat<f64>,
+    /// Rated output current
+    pub current: u16,
+    /// Rated battery voltage
+    pub battery_voltage: OrderedFloat<f64>,
+    /// Rated output frequency
+    pub frequency: OrderedFloat<f64>,
+}
+
+/// Kind of battery test to run on the device
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Validate, FromJsonQueryResult,
+)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum BatteryTestMode {
+    /// Quick 10 second battery test
+    Quick,
+    /// Battery test for a number of minutes
+    Minutes {
+        /// Minutes to run the test for
+        #[garde(range(min = 1, max = 99))]
+        minutes: u8,
+    },
+    /// Battery test until the battery is low
+    UntilLow,
+}
+
+/// What the UPS should do after a scheduled shutdown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Validate)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UPSShutdownRestore {
+    /// Turn back on after a number of minutes
+    After {
+        /// Minutes to wait before turning back on
+        #[garde(range(min = 1, max = 9999))]
+        minutes: u16,
+    },
+    /// Stay off until manually turned back on
+    StayOff,
+}
+
+impl Default for UPSShutdownRestore {
+    fn default() -> Self {
+        Self::After { minutes: 1 }
+    }
+}
+
+/// UPS shutdown that has been scheduled on the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PendingUPSShutdown {
+    /// Minutes from when the shutdown was scheduled until the UPS shuts down
+    pub delay_minutes: OrderedFloat<f32>,
+    /// What the UPS will do after shutting down
+    pub restore: UPSShutdownRestore,
+    /// When the shutdown was scheduled
+    pub scheduled_at: DateTime<Utc>,
+    /// When the UPS will shutdown
+    pub shutdown_at: DateTime<Utc>,
+}
+
+impl PendingUPSShutdown {
+    /// When the shutdown is over, either when the UPS turns back on
+    /// or when the UPS shuts down if it stays off
+    pub fn completed_at(&self) -> DateTime<Utc> {
+        match self.restore {
+            UPSShutdownRestore::After { minutes } => {
+                self.shutdown_at + TimeDelta::minutes(minutes as i64)
+            }
+            UPSShutdownRestore::StayOff => self.shutdown_at,
+        }
+    }
+}
@@ -0,0 +1,352 @@
+//! # Protocols
+//!
+//! Voltronic/Megatec based devices share most of their commands but report their
+//! state using different wire dialects. The [Protocol] sits between the executor and
+//! the typed commands, commands that differ between the dialects (i.e
+//! [QueryDeviceState](super::QueryDeviceState) and [QueryDeviceBattery](super::QueryDeviceBattery))
+//! ask the protocol which command to send and how to parse the response.
+//!
+//! Megatec `Q1` devices have no battery query, their battery capacity is estimated
+//! from the battery voltage reported by the state query
+//!
+//! The protocol a device speaks is determined when the device is connected by
+//! probing each of the known state queries until one is answered, NUT devices
+//! always speak the [Protocol::Nut] protocol and are never probed
+
+use compact_str::CompactString;
+use log::debug;
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+use super::{
+    command::{FromDeviceResponse, ResponseFields},
+    command::{ParseErrorKind, ResponseParseError},
+    device::Device,
+    models::{DeviceBattery, DeviceInfo, DeviceLineType, DevicePowerState, DeviceState},
+};
+
+/// Voltage of a single battery cell when the battery is empty, matches the
+/// low battery voltage NUT assumes for Megatec devices (10.4V for a 12V battery)
+const CELL_EMPTY_VOLTAGE: f64 = 1.733;
+
+/// Voltage of a single battery cell when the battery is full (13.0V for a 12V battery)
+const CELL_FULL_VOLTAGE: f64 = 2.167;
+
+/// Nominal voltage of each battery block
+const BLOCK_VOLTAGE: f64 = 12.0;
+
+/// Number of cells in each battery block
+const CELLS_PER_BLOCK: f64 = 6.0;
+
+/// Wire dialect used to query the device state
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "UPPERCASE")]
+pub enum Protocol {
+    /// PMV_H dialect, device state from the `QS` query
+    #[default]
+    Qs,
+    /// Megatec dialect used by older units, device state from the `Q1` query
+    Q1,
+    /// Voltronic dialect used by on-line units, device state from the `QGS` query
+    Qgs,
+    /// Responses translated from the variables of a NUT server, same as [Protocol::Qs]
+    /// except the device info fields are tab separated instead of fixed width so
+    /// long names aren't truncated
+    Nut,
+}
+
+impl Protocol {
+    /// All the known protocols in the order they are probed
+    pub const ALL: [Protocol; 3] = [Protocol::Qs, Protocol::Q1, Protocol::Qgs];
+
+    /// Command used to query the device state
+    pub fn state_command(&self) -> &'static str {
+        match self {
+            Protocol::Qs | Protocol::Nut => "QS",
+            Protocol::Q1 => "Q1",
+            Protocol::Qgs => "QGS",
+        }
+    }
+
+    /// Parses the response to the [Protocol::state_command]
+    pub fn parse_state(&self, value: CompactString) -> anyhow::Result<DeviceState> {
+        match self {
+            // Q1 responses share the same layout as QS
+            Protocol::Qs | Protocol::Q1 | Protocol::Nut => DeviceState::from_device_response(value),
+            Protocol::Qgs => parse_qgs_state(&value),
+        }
+    }
+
+    /// Command used to query the device battery
+    pub fn battery_command(&self) -> &'static str {
+        match self {
+            Protocol::Qs | Protocol::Nut => "QI",
+            // Battery is estimated from the device state
+            Protocol::Q1 => "Q1",
+            Protocol::Qgs => "QBV",
+        }
+    }
+
+    /// Parses the response to the [Protocol::battery_command]
+    pub fn parse_battery(&self, value: CompactString) -> anyhow::Result<DeviceBattery> {
+        match self {
+            Protocol::Qs | Protocol::Nut => DeviceBattery::from_device_response(value),
+            Protocol::Q1 => parse_q1_battery(value),
+            Protocol::Qgs => parse_qbv_battery(&value),
+        }
+    }
+
+    /// Parses the response to the device info query
+    pub fn parse_info(&self, value: CompactString) -> anyhow::Result<DeviceInfo> {
+        match self {
+            Protocol::Nut => parse_nut_info(&value),
+            _ => DeviceInfo::from_device_response(value),
+        }
+    }
+
+    /// Finds the protocol spoken by the device by sending the state query for
+    /// each of the protocols, provides [None] if none of the queries could be
+    /// parsed. Errors are only provided if the device was disconnected
+    pub fn probe<D: Device>(device: &mut D) -> anyhow::Result<Option<Protocol>> {
+        for protocol in Protocol::ALL {
+            let result = device
+                .send_command(protocol.state_command())
+                .and_then(|response| protocol.parse_state(response));
+
+            match result {
+                Ok(_) => return Ok(Some(protocol)),
+                Err(err) if device.is_disconnect_error(&err) => return Err(err),
+                Err(err) => debug!("device did not answer {protocol} protocol: {err:#}"),
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Finds the protocol for the provided command if it is
+    /// one of the protocol state queries
+    pub fn from_state_command(command: &str) -> Option<Protocol> {
+        Protocol::ALL
+            .into_iter()
+            .find(|protocol| protocol.state_command() == command)
+    }
+}
+
+/// Estimates the battery from a Q1 device state response, Q1 devices don't report
+/// the remaining time so it is always zero.
+///
+/// On-line units report the voltage of a single cell (i.e `2.05`) while standby units
+/// report the voltage of the whole battery (i.e `27.2`) which is assumed to be made
+/// of 12V blocks
+fn parse_q1_battery(value: CompactString) -> anyhow::Result<DeviceBattery> {
+    let state = Protocol::Q1.parse_state(value)?;
+    let voltage = state.battery_voltage.0;
+
+    // Single cells never reach the voltage of a whole battery block
+    let cell_voltage = if voltage < BLOCK_VOLTAGE / CELLS_PER_BLOCK * 1.5 {
+        voltage
+    } else {
+        let blocks = (voltage / BLOCK_VOLTAGE).round().max(1.0);
+        voltage / (blocks * CELLS_PER_BLOCK)
+    };
+
+    let capacity = (cell_voltage - CELL_EMPTY_VOLTAGE) / (CELL_FULL_VOLTAGE - CELL_EMPTY_VOLTAGE);
+
+    Ok(DeviceBattery {
+        capacity: (capacity.clamp(0.0, 1.0) * 100.0).round() as u8,
+        remaining_time: 0,
+    })
+}
+
+/// Parses a QBV battery response
+fn parse_qbv_battery(msg: &CompactString) -> anyhow::Result<DeviceBattery> {
+    // 026.5 02 01 068 255
+    let mut fields = ResponseFields::new(msg, '(')?;
+
+    fields.next_str("battery voltage")?;
+    fields.next_str("battery packs")?;
+    fields.next_str("battery parallel packs")?;
+    let capacity = fields.next("capacity")?;
+    let remaining_minutes: u32 = fields.next("battery remaining")?;
+
+    Ok(DeviceBattery {
+        capacity,
+        remaining_time: remaining_minutes * 60,
+    })
+}
+
+/// Parses a NUT device info response
+fn parse_nut_info(msg: &CompactString) -> anyhow::Result<DeviceInfo> {
+    // #American Power Conversion\tBack-UPS 700\t871.O2
+    let value: &str = msg
+        .strip_prefix('#')
+        .ok_or_else(|| ResponseParseError::new(msg, "prefix", 0, ParseErrorKind::Missing))?;
+
+    let mut fields = value.split('\t').map(|value| value.trim().to_string());
+    let mut next_field = || fields.next().unwrap_or_default();
+
+    let company = next_field();
+    let model = next_field();
+    let version = next_field();
+
+    if company.is_empty() && model.is_empty() && version.is_empty() {
+        return Err(ResponseParseError::new(msg, "company", 0, ParseErrorKind::Missing).into());
+    }
+
+    Ok(DeviceInfo {
+        company,
+        model,
+        version,
+    })
+}
+
+/// Parses a QGS device state response
+fn parse_qgs_state(msg: &CompactString) -> anyhow::Result<DeviceState> {
+    // 234.9 50.0 229.8 50.0 000.0 000 369.1 356.3 027.2 ---.- 022.7 100000000001
+    let mut fields = ResponseFields::new(msg, '(')?;
+
+    let input_voltage: OrderedFloat<f64> = fields.next("input voltage")?;
+    fields.next_str("input frequency")?;
+    let output_voltage: OrderedFloat<f64> = fields.next("output voltage")?;
+    let output_frequency: OrderedFloat<f64> = fields.next("output frequency")?;
+    fields.next_str("output current")?;
+    let output_load_percent: u8 = fields.next("output load percent")?;
+    fields.next_str("positive bus voltage")?;
+    fields.next_str("negative bus voltage")?;
+    let battery_voltage: OrderedFloat<f64> = fields.next("battery voltage")?;
+    fields.next_str("negative battery voltage")?;
+    let temperature = fields.next_optional("temperature")?;
+    let status = fields.next_str("status")?;
+
+    // Ten status bits followed by two bits for the device type
+    let status: Vec<char> = status.chars().collect();
+    if status.len() != 12 {
+        return Err(fields.invalid("status").into());
+    }
+
+    let device_power_state = match status[0] {
+        '0' => DevicePowerState::Utility,
+        '1' => DevicePowerState::Battery,
+        _ => return Err(fields.invalid("status").into()),
+    };
+
+    let device_line_type = match (status[10], status[11]) {
+        ('1', '0') => DeviceLineType::OnLine,
+        _ => DeviceLineType::LineInteractive,
+    };
+
+    Ok(DeviceState {
+        input_voltage,
+        input_fault_voltage: None,
+        output_voltage,
+        output_load_percent,
+        output_frequency,
+        battery_voltage,
+        temperature,
+        device_power_state,
+        battery_low: status[1] == '1',
+        bypass_boost_active: status[2] == '1',
+        fault_mode: status[3] == '1',
+        device_line_type,
+        battery_self_test: status[5] == '1',
+        shutdown_active: status[6] == '1',
+        // Bit is set when the buzzer has been silenced
+        buzzer_control: status[7] == '0',
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use ordered_float::OrderedFloat;
+
+    use super::Protocol;
+    use crate::ups::{
+        MockDeviceCreator,
+        device::DeviceCreator,
+        models::{DeviceLineType, DevicePowerState},
+    };
+
+    /// Q1 responses should parse using the QS layout
+    #[test]
+    fn test_parse_q1_state() {
+        let value = "(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000";
+        let state = Protocol::Q1.parse_state(value.into()).unwrap();
+
+        assert_eq!(state.input_voltage, OrderedFloat(208.4));
+        assert_eq!(state.battery_voltage, OrderedFloat(2.05));
+        assert_eq!(state.device_power_state, DevicePowerState::Utility);
+        assert!(state.bypass_boost_active);
+        assert!(state.fault_mode);
+    }
+
+    /// Should parse a valid QGS device state
+    #[test]
+    fn test_parse_qgs_state() {
+        let value = "(234.9 50.0 229.8 50.0 000.0 012 369.1 356.3 027.2 ---.- 022.7 110000000010";
+        let state = Protocol::Qgs.parse_state(value.into()).unwrap();
+
+        assert_eq!(state.input_voltage, OrderedFloat(234.9));
+        assert_eq!(state.output_voltage, OrderedFloat(229.8));
+        assert_eq!(state.output_load_percent, 12);
+        assert_eq!(state.battery_voltage, OrderedFloat(27.2));
+        assert_eq!(state.temperature, Some(OrderedFloat(22.7)));
+        assert_eq!(state.device_power_state, DevicePowerState::Battery);
+        assert_eq!(state.device_line_type, DeviceLineType::OnLine);
+        assert!(state.battery_low);
+        assert!(state.buzzer_control);
+    }
+
+    /// Should fail on a malformed response
+    #[test]
+    fn test_fail_parse_qgs_state() {
+        let value = "(234.9 50.0 229.8 50.0 000.0 012 369.1 356.3 027.2 ---.- 022.7 00001001";
+        Protocol::Qgs
+            .parse_state(value.into())
+            .expect_err("State should fail parsing");
+    }
+
+    /// Q1 battery should be estimated from the per cell or total battery voltage
+    #[test]
+    fn test_parse_q1_battery() {
+        let cell = "(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000";
+        let battery = Protocol::Q1.parse_battery(cell.into()).unwrap();
+        assert_eq!(battery.capacity, 73);
+        assert_eq!(battery.remaining_time, 0);
+
+        let total = "(208.4 140.0 208.4 034 59.9 27.2 35.0 00110000";
+        let battery = Protocol::Q1.parse_battery(total.into()).unwrap();
+        assert_eq!(battery.capacity, 100);
+
+        let empty = "(208.4 140.0 208.4 034 59.9 20.4 35.0 10110000";
+        let battery = Protocol::Q1.parse_battery(empty.into()).unwrap();
+        assert_eq!(battery.capacity, 0);
+    }
+
+    /// Should parse a valid QBV battery
+    #[test]
+    fn test_parse_qbv_battery() {
+        let value = "(026.5 02 01 068 255";
+        let battery = Protocol::Qgs.parse_battery(value.into()).unwrap();
+
+        assert_eq!(battery.capacity, 68);
+        assert_eq!(battery.remaining_time, 255 * 60);
+    }
+
+    /// Probing should pick the first protocol the device answers
+    #[test]
+    fn test_probe() {
+        let (creator, mut handle) = MockDeviceCreator::new();
+        let mut device = creator.try_create_device().unwrap();
+
+        handle.next_response("(NAK".into());
+        handle.next_response("(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000".into());
+
+        let protocol = Protocol::probe(&mut device).unwrap();
+        assert_eq!(protocol, Some(Protocol::Q1));
+        assert_eq!(handle.try_next_command().as_deref(), Some("QS"));
+        assert_eq!(handle.try_next_command().as_deref(), Some("Q1"));
+        assert!(handle.try_next_command().is_none());
+    }
+}
@@ -0,0 +1,103 @@
+//! # Device Registry
+//!
+//! Registry of the UPS devices monitored by this instance keyed by their
+//! device ID, each device has its own [DeviceExecutorHandle]. The first
+//! device in the registry is the primary device used by routes that don't
+//! specify a device.
+
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use super::{
+    device::{DefaultDevice, Device, SharedSimulation},
+    executor::DeviceExecutorHandle,
+};
+
+/// Unique ID for a device from the configuration
+pub type DeviceId = String;
+
+/// ID used by the primary device when one is not configured
+pub const DEFAULT_DEVICE_ID: &str = "default";
+
+/// Shared reference to a [DeviceRegistry]
+pub type SharedDeviceRegistry<D = DefaultDevice> = Arc<DeviceRegistry<D>>;
+
+/// Registry of the devices being monitored
+pub struct DeviceRegistry<D: Device = DefaultDevice> {
+    /// Devices in the order they were configured
+    devices: Vec<RegisteredDevice<D>>,
+}
+
+/// Device within a [DeviceRegistry]
+pub struct RegisteredDevice<D: Device = DefaultDevice> {
+    /// Unique ID of the device
+    pub id: DeviceId,
+    /// Executor for sending commands to the device
+    pub executor: DeviceExecutorHandle<D>,
+    /// Simulation steering the device, only present for simulated devices
+    pub simulation: Option<SharedSimulation>,
+}
+
+/// Details about a registered device
+#[derive(Debug, Serialize)]
+pub struct DeviceDetails {
+    /// Unique ID of the device
+    pub id: DeviceId,
+    /// Whether this is the primary device
+    pub primary: bool,
+    /// Whether this is a simulated device
+    pub simulated: bool,
+    /// Whether the device is currently connected
+    pub connected: bool,
+}
+
+impl<D: Device> DeviceRegistry<D> {
+    /// Creates a new registry from the provided devices, the first device will
+    /// become the primary device
+    ///
+    /// Fails if no devices are provided or if device IDs are not unique
+    pub fn new(devices: Vec<RegisteredDevice<D>>) -> anyhow::Result<Self> {
+        if devices.is_empty() {
+            return Err(anyhow::anyhow!("at least one device must be configured"));
+        }
+
+        for (index, device) in devices.iter().enumerate() {
+            if devices[..index].iter().any(|other| other.id == device.id) {
+                return Err(anyhow::anyhow!("duplicate device id: {}", device.id));
+            }
+        }
+
+        Ok(Self { devices })
+    }
+
+    /// Gets the primary device
+    pub fn primary(&self) -> &RegisteredDevice<D> {
+        // Registry cannot be created without at least one device
+        &self.devices[0]
+    }
+
+    /// Gets a device by ID
+    pub fn get(&self, id: &str) -> Option<&RegisteredDevice<D>> {
+        self.devices.iter().find(|device| device.id == id)
+    }
+
+    /// Iterates the registered devices
+    pub fn iter(&self) -> impl Iterator<Item = &RegisteredDevice<D>> {
+        self.devices.iter()
+    }
+
+    /// Provides details about each of the registered devices
+    pub fn details(&self) -> Vec<DeviceDetails> {
+        self.devices
+            .iter()
+            .enumerate()
+            .map(|(index, device)| DeviceDetails {
+                id: device.id.clone(),
+                primary: index == 0,
+                simulated: device.simulation.is_some(),
+                connected: device.executor.is_connected(),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,53 @@
+//! # Devices
+//!
+//! CLI helpers for finding the UPS device to use, lists the connected
+//! HID devices so the correct device can be chosen in the config
+
+use anyhow::Context;
+use hidapi::HidApi;
+
+use crate::config::HidConfig;
+
+/// Prints the connected HID devices, by default only devices matching the
+/// configured vendor and product IDs are listed unless `all` is set
+pub fn list_devices(config: &HidConfig, all: bool) -> anyhow::Result<()> {
+    let api = HidApi::new().context("failed to create hid api")?;
+
+    let devices: Vec<_> = api
+        .device_list()
+        .filter(|device| {
+            all || (device.vendor_id() == config.vendor_id
+                && device.product_id() == config.product_id)
+        })
+        .collect();
+
+    if devices.is_empty() {
+        if all {
+            println!("No HID devices found");
+        } else {
+            println!(
+                "No HID devices found matching {:04x}:{:04x} (use --all to list every device)",
+                config.vendor_id, config.product_id
+            );
+        }
+        return Ok(());
+    }
+
+    for device in devices {
+        println!(
+            "{:04x}:{:04x} {} {}",
+            device.vendor_id(),
+            device.product_id(),
+            device.manufacturer_string().unwrap_or("Unknown"),
+            device.product_string().unwrap_or("Unknown"),
+        );
+        println!("  path: {}", device.path().to_string_lossy());
+        println!(
+            "  serial_number: {}",
+            device.serial_number().unwrap_or("None")
+        );
+        println!("  interface: {}", device.interface_number());
+    }
+
+    Ok(())
+}
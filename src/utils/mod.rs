@@ -2,6 +2,7 @@
 //!
 //! Utility modules and windows platform specific service code
 
+pub mod devices;
 pub mod validate;
 
 #[cfg(target_os = "windows")]
@@ -5,8 +5,11 @@
 
 use std::time::Duration;
 
+use ordered_float::OrderedFloat;
 use sea_orm::prelude::DateTimeUtc;
 
+use crate::ups::ScheduleUPSShutdown;
+
 /// Validates the duration is greater than zero
 pub fn is_non_zero_duration(value: &Duration, _ctx: &()) -> garde::Result {
     if value.as_secs() < 1 {
@@ -26,3 +29,16 @@ pub fn valid_range(start: &DateTimeUtc) -> impl FnOnce(&DateTimeUtc, &()) -> gar
         Ok(())
     }
 }
+
+/// Validates the UPS shutdown delay is within the range supported by the device
+pub fn is_valid_ups_shutdown_delay(value: &OrderedFloat<f32>, _ctx: &()) -> garde::Result {
+    let range = ScheduleUPSShutdown::MIN_DELAY_MINUTES..=ScheduleUPSShutdown::MAX_DELAY_MINUTES;
+    if !range.contains(&value.0) {
+        return Err(garde::Error::new(format!(
+            "shutdown delay must be between {} and {} minutes",
+            range.start(),
+            range.end()
+        )));
+    }
+    Ok(())
+}
@@ -11,7 +11,8 @@ export const BATTERY_INFO_KEY: [string] = ['battery-info'];
 export function startBatteryTestRequest(): Promise<string> {
 	return requestText({
 		method: HttpMethod.POST,
-		route: '/api/test-battery/start'
+		route: '/api/test-battery/start',
+		body: { mode: 'quick' }
 	});
 }
 
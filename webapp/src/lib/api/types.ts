@@ -15,15 +15,19 @@ export enum DeviceLineType {
 
 export interface DeviceState {
 	input_voltage: number;
+	input_fault_voltage: number | null;
 	output_voltage: number;
 	output_load_percent: number;
 	output_frequency: number;
 	battery_voltage: number;
+	temperature: number | null;
 	device_power_state: DevicePowerState;
 	battery_low: boolean;
+	bypass_boost_active: boolean;
 	fault_mode: boolean;
 	device_line_type: DeviceLineType;
 	battery_self_test: boolean;
+	shutdown_active: boolean;
 	buzzer_control: boolean;
 }
 
@@ -31,20 +35,34 @@ export enum EventType {
 	ACFailure = 'ACFailure',
 	ACRecovery = 'ACRecovery',
 	UPSFault = 'UPSFault',
+	UPSFaultCleared = 'UPSFaultCleared',
 	LowBatteryModeStart = 'LowBatteryModeStart',
 	LowBatteryModeEnd = 'LowBatteryModeEnd',
 	BatteryTestStart = 'BatteryTestStart',
-	BatteryTestEnd = 'BatteryTestEnd'
+	BatteryTestEnd = 'BatteryTestEnd',
+	DeviceDisconnected = 'DeviceDisconnected',
+	DeviceReconnected = 'DeviceReconnected',
+	OverloadStart = 'OverloadStart',
+	OverloadEnd = 'OverloadEnd',
+	PowerUnstable = 'PowerUnstable',
+	ServiceStarted = 'ServiceStarted'
 }
 
 export const EVENT_TYPES = [
 	EventType.ACFailure,
 	EventType.ACRecovery,
 	EventType.UPSFault,
+	EventType.UPSFaultCleared,
 	EventType.LowBatteryModeStart,
 	EventType.LowBatteryModeEnd,
 	EventType.BatteryTestStart,
-	EventType.BatteryTestEnd
+	EventType.BatteryTestEnd,
+	EventType.DeviceDisconnected,
+	EventType.DeviceReconnected,
+	EventType.OverloadStart,
+	EventType.OverloadEnd,
+	EventType.PowerUnstable,
+	EventType.ServiceStarted
 ];
 
 export enum EventLevel {
@@ -68,6 +86,9 @@ export const EVENT_TYPE_DATA: Record<EventType, EventTypeData> = {
 	[EventType.UPSFault]: {
 		level: EventLevel.Severe
 	},
+	[EventType.UPSFaultCleared]: {
+		level: EventLevel.Success
+	},
 	[EventType.LowBatteryModeStart]: {
 		level: EventLevel.Severe
 	},
@@ -79,27 +100,166 @@ export const EVENT_TYPE_DATA: Record<EventType, EventTypeData> = {
 	},
 	[EventType.BatteryTestEnd]: {
 		level: EventLevel.Success
+	},
+	[EventType.DeviceDisconnected]: {
+		level: EventLevel.Severe
+	},
+	[EventType.DeviceReconnected]: {
+		level: EventLevel.Success
+	},
+	[EventType.OverloadStart]: {
+		level: EventLevel.Warning
+	},
+	[EventType.OverloadEnd]: {
+		level: EventLevel.Success
+	},
+	[EventType.PowerUnstable]: {
+		level: EventLevel.Warning
+	},
+	[EventType.ServiceStarted]: {
+		level: EventLevel.Info
 	}
 };
 
+export interface DeviceInfo {
+	company: string;
+	model: string;
+	version: string;
+}
+
+export interface DeviceRating {
+	voltage: number;
+	current: number;
+	battery_voltage: number;
+	frequency: number;
+}
+
+export interface DeviceInfoResponse {
+	info: DeviceInfo;
+	rating: DeviceRating;
+}
+
+export type BatteryTestMode =
+	| { mode: 'quick' }
+	| { mode: 'minutes'; minutes: number }
+	| { mode: 'until_low' };
+
+export type UPSShutdownRestore = { type: 'after'; minutes: number } | { type: 'stay_off' };
+
+export interface ScheduleUPSShutdownRequest {
+	delay_minutes: number;
+	restore?: UPSShutdownRestore;
+}
+
+export interface UPSShutdownStatus {
+	delay_minutes: number;
+	restore: UPSShutdownRestore;
+	scheduled_at: string;
+	shutdown_at: string;
+	remaining_seconds: number;
+}
+
+export type DeviceId = string;
+
+export interface DeviceDetails {
+	id: DeviceId;
+	primary: boolean;
+	simulated: boolean;
+	connected: boolean;
+}
+
+export interface SimulationStatus {
+	ac_power: boolean;
+	load_percent: number;
+	capacity: number;
+	fault: boolean;
+	buzzer: boolean;
+	self_test: boolean;
+	shutdown: boolean;
+	output_off: boolean;
+}
+
+export type SimulationControl = Partial<{
+	ac_power: boolean;
+	load_percent: number;
+	capacity: number;
+	fault: boolean;
+}>;
+
+export interface RawCommandRequest {
+	command: string;
+}
+
+export interface RawCommandResponse {
+	command: string;
+	response: string;
+}
+
+export enum ParseErrorKind {
+	Missing = 'missing',
+	Invalid = 'invalid'
+}
+
+export interface ParseFailure {
+	response: string;
+	field: string;
+	index: number;
+	kind: ParseErrorKind;
+	failed_at: string;
+}
+
+export interface ParseFailures {
+	count: number;
+	last_failure: ParseFailure;
+}
+
+export type ParseFailuresResponse = Record<string, ParseFailures>;
+
+export interface CommandHealth {
+	count: number;
+	error_count: number;
+	cache_hits: number;
+	cache_hit_rate: number;
+	average_latency_ms: number | null;
+	p95_latency_ms: number | null;
+	last_success_at: string | null;
+}
+
+export interface DeviceHealth {
+	connected: boolean;
+	reconnects: number;
+	last_success_at: string | null;
+	commands: Record<string, CommandHealth>;
+}
+
 export interface DeviceStateHistory {
 	id: number;
+	device_id: DeviceId;
 	state: DeviceState;
 	created_at: string;
 }
 
 export interface DeviceBatteryHistory {
 	id: number;
+	device_id: DeviceId;
 	state: DeviceBattery;
 	created_at: string;
 }
 
 export interface EventHistory {
 	id: number;
+	device_id: DeviceId;
 	type: EventType;
+	battery_test?: BatteryTestMode;
+	snapshot?: DeviceSnapshot;
 	created_at: string;
 }
 
+export interface DeviceSnapshot {
+	state: DeviceState;
+	battery: DeviceBattery | null;
+}
+
 export enum ActionTypeKey {
 	Notification = 'Notification',
 	Popup = 'Popup',
@@ -135,7 +295,11 @@ export type ActionType =
 			timeout: Duration | null;
 			force_close_apps: boolean;
 	  }
-	| { type: ActionTypeKey.USPShutdown; delay_minutes: number }
+	| {
+			type: ActionTypeKey.USPShutdown;
+			delay_minutes: number;
+			restore?: UPSShutdownRestore;
+	  }
 	| { type: ActionTypeKey.Executable; exe: string; args: string[]; timeout: Duration | null }
 	| {
 			type: ActionTypeKey.HttpRequest;
@@ -168,7 +332,11 @@ export function getDefaultActionType(actionType: ActionTypeKey): ActionType {
 				force_close_apps: false
 			};
 		case ActionTypeKey.USPShutdown:
-			return { type: ActionTypeKey.USPShutdown, delay_minutes: 1 };
+			return {
+				type: ActionTypeKey.USPShutdown,
+				delay_minutes: 1,
+				restore: { type: 'after', minutes: 1 }
+			};
 		case ActionTypeKey.Executable:
 			return { type: ActionTypeKey.Executable, exe: 'notepad.exe', args: [], timeout: null };
 		case ActionTypeKey.HttpRequest:
@@ -268,6 +436,7 @@ export type ListEventPipeline = {
 	id: PipelineId;
 	name: string;
 	event: EventType;
+	device_id: DeviceId | null;
 	cancellable: boolean;
 	enabled: boolean;
 	created_at: string;
@@ -279,6 +448,7 @@ export type EventPipeline = {
 	id: PipelineId;
 	name: string;
 	event: EventType;
+	device_id: DeviceId | null;
 	pipeline: ActionPipeline;
 	cancellable: boolean;
 	enabled: boolean;
@@ -290,6 +460,7 @@ export type EventPipeline = {
 export type CreateEventPipeline = {
 	name: string;
 	event: EventType;
+	device_id?: DeviceId | null;
 	pipeline: ActionPipeline;
 	cancellable: boolean;
 };
@@ -297,6 +468,7 @@ export type CreateEventPipeline = {
 export type UpdateEventPipeline = Partial<{
 	name: string;
 	event: EventType;
+	device_id: DeviceId | null;
 	pipeline: ActionPipeline;
 	cancellable: boolean;
 	enabled: boolean;